@@ -5,12 +5,16 @@ use std::{
 };
 
 use rand::distributions::{Alphanumeric, DistString};
-use rrc_lib::files::overwrite_file;
+use rrc_lib::files::{
+    open_for_overwrite, overwrite_file, OverwriteOptions, OverwritePattern, WriteBackend,
+};
 
 use criterion::BenchmarkId;
 use criterion::Criterion;
 use criterion::{criterion_group, criterion_main};
 
+const MB: usize = 10usize.pow(6);
+
 fn generate_random_filename() -> String {
     Alphanumeric.sample_string(&mut rand::thread_rng(), 8)
         + "."
@@ -21,6 +25,7 @@ fn gen_file(size: usize, name: &str) -> File {
     let mut file = OpenOptions::new()
         .write(true)
         .create(true)
+        .truncate(true)
         .read(true)
         .open(name)
         .unwrap();
@@ -33,7 +38,6 @@ fn gen_file(size: usize, name: &str) -> File {
 }
 
 fn bench_overwrite(c: &mut Criterion) {
-    let MB = 10usize.pow(6);
     let mut group = c.benchmark_group("overwrite-file");
     group.measurement_time(Duration::from_secs(5));
 
@@ -43,11 +47,104 @@ fn bench_overwrite(c: &mut Criterion) {
         group.bench_with_input(
             BenchmarkId::from_parameter((size / MB).to_string()),
             &file,
-            |b, f| b.iter(|| overwrite_file(f, 1)),
+            |b, f| b.iter(|| overwrite_file(f, &OverwriteOptions::default())),
         );
         std::fs::remove_file(filename).unwrap();
     }
 }
 
-criterion_group!(benches, bench_overwrite);
+fn bench_buffer_size(c: &mut Criterion) {
+    let mut group = c.benchmark_group("buffer-size");
+    group.measurement_time(Duration::from_secs(5));
+
+    let filename = &generate_random_filename();
+    let file = gen_file(100 * MB, filename);
+    for (label, buffer_size) in [
+        ("64K", 64 << 10),
+        ("256K", 256 << 10),
+        ("1M", 1 << 20),
+        ("4M", 4 << 20),
+        ("16M", 16 << 20),
+    ] {
+        let opts = OverwriteOptions {
+            buffer_size,
+            ..Default::default()
+        };
+        group.bench_with_input(BenchmarkId::from_parameter(label), &file, |b, f| {
+            b.iter(|| overwrite_file(f, &opts))
+        });
+    }
+    std::fs::remove_file(filename).unwrap();
+}
+
+fn bench_pattern(c: &mut Criterion) {
+    let mut group = c.benchmark_group("pattern");
+    group.measurement_time(Duration::from_secs(5));
+
+    let filename = &generate_random_filename();
+    let file = gen_file(100 * MB, filename);
+    for pattern in [
+        OverwritePattern::Zeroes,
+        OverwritePattern::Ones,
+        OverwritePattern::Random,
+    ] {
+        let opts = OverwriteOptions {
+            pattern,
+            ..Default::default()
+        };
+        group.bench_with_input(
+            BenchmarkId::from_parameter(pattern.name()),
+            &file,
+            |b, f| b.iter(|| overwrite_file(f, &opts)),
+        );
+    }
+    std::fs::remove_file(filename).unwrap();
+}
+
+fn bench_backend(c: &mut Criterion) {
+    let mut group = c.benchmark_group("backend");
+    group.measurement_time(Duration::from_secs(5));
+
+    let filename = &generate_random_filename();
+    drop(gen_file(100 * MB, filename));
+
+    let mut backends = vec![
+        ("positional", OverwriteOptions::default()),
+        (
+            "direct",
+            OverwriteOptions {
+                direct_io: true,
+                ..Default::default()
+            },
+        ),
+    ];
+    if cfg!(feature = "io-uring") {
+        backends.push((
+            "io-uring",
+            OverwriteOptions {
+                backend: WriteBackend::IoUring,
+                ..Default::default()
+            },
+        ));
+    }
+
+    for (label, opts) in backends {
+        //Filesystems without O_DIRECT support just don't get that backend benchmarked
+        let Ok(file) = open_for_overwrite(filename, &opts) else {
+            continue;
+        };
+        group.bench_with_input(BenchmarkId::from_parameter(label), &file, |b, f| {
+            b.iter(|| overwrite_file(f, &opts))
+        });
+    }
+    std::fs::remove_file(filename).unwrap();
+}
+
+criterion_group!(
+    benches,
+    bench_overwrite,
+    bench_buffer_size,
+    bench_pattern,
+    bench_backend
+);
 criterion_main!(benches);
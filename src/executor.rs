@@ -0,0 +1,211 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use clap::ArgMatches;
+use rrc_lib::{
+    files::{self, WalkOptions},
+    plan::{FileIdentity, Plan, PlanAction, PlanEntry},
+    protect::Protection,
+};
+use trash::TrashItem;
+
+use crate::{
+    operations::OPERATION,
+    output::{self, OpSpinner},
+};
+
+///An action a dry run would have carried out
+pub struct PlannedAction {
+    pub operation: OPERATION,
+    pub target: String,
+    pub size: u64,
+    ///What goes into a plan written out for the action, if it's one that can be applied later
+    pub entry: Option<PlanEntry>,
+}
+
+///Every change an operation makes to files or the trash goes through here, so that a dry run takes exactly the
+/// same path through an operation as a real one. In a dry run the changes are only recorded, and listed once the
+/// operation finishes, or written out as a plan which can be applied later
+#[derive(Default)]
+pub struct Executor {
+    dry_run: bool,
+    plan_out: Option<PathBuf>,
+    planned: Mutex<Vec<PlannedAction>>,
+}
+
+impl Executor {
+    pub fn new(dry_run: bool) -> Executor {
+        Executor {
+            dry_run,
+            ..Default::default()
+        }
+    }
+
+    ///Making a plan is a dry run which writes out what it would have done
+    pub fn from_args(args: &ArgMatches) -> Executor {
+        let plan_out = args.get_one::<String>("plan_out").map(PathBuf::from);
+        Executor {
+            dry_run: args.get_flag("dry_run") || plan_out.is_some(),
+            plan_out,
+            ..Default::default()
+        }
+    }
+
+    pub fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    ///Runs an action on a path. In a dry run the action is recorded instead and None is returned
+    pub fn on_path<T, E>(
+        &self,
+        operation: OPERATION,
+        path: &Path,
+        action: impl FnOnce() -> Result<T, E>,
+    ) -> Result<Option<T>, E> {
+        self.execute(
+            operation,
+            || files::path_to_string(path),
+            || path_size(path),
+            || {
+                let action = match operation {
+                    OPERATION::TRASH => PlanAction::Trash,
+                    OPERATION::DELETE => PlanAction::Delete,
+                    OPERATION::SHRED { .. } => PlanAction::Shred,
+                    _ => return None,
+                };
+                Some(PlanEntry {
+                    action,
+                    path: std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()),
+                    trash_id: None,
+                    identity: FileIdentity::of(path).ok(),
+                })
+            },
+            action,
+        )
+    }
+
+    ///Runs an action which takes everything under a path along with it, like moving it to the trash. A dry run
+    /// lists everything under the path too, so that the whole tree counts towards the size
+    pub fn on_tree<T, E>(
+        &self,
+        operation: OPERATION,
+        path: &Path,
+        action: impl FnOnce() -> Result<T, E>,
+    ) -> Result<Option<T>, E> {
+        if self.dry_run {
+            let mut entries = files::tree_entries(path);
+            //The path itself is recorded along with its plan entry below
+            entries.pop();
+            self.planned
+                .lock()
+                .unwrap()
+                .extend(entries.into_iter().map(|(p, size)| PlannedAction {
+                    operation,
+                    target: files::path_to_string(p),
+                    size,
+                    entry: None,
+                }));
+        }
+        self.on_path(operation, path, action)
+    }
+
+    ///Runs an action on an item in the trash, which is listed by its original path in a dry run
+    pub fn on_trash_item<T, E>(
+        &self,
+        operation: OPERATION,
+        item: &TrashItem,
+        action: impl FnOnce() -> Result<T, E>,
+    ) -> Result<Option<T>, E> {
+        self.execute(
+            operation,
+            || files::path_to_string(item.original_path()),
+            || trash_item_size(item),
+            || {
+                Some(PlanEntry {
+                    action: match operation {
+                        OPERATION::RESTORE => PlanAction::Restore,
+                        _ => PlanAction::Purge,
+                    },
+                    path: item.original_path(),
+                    trash_id: Some(item.id.to_string_lossy().into_owned()),
+                    identity: files::trash_item_backing_path(item)
+                        .and_then(|p| FileIdentity::of(&p).ok()),
+                })
+            },
+            action,
+        )
+    }
+
+    ///Runs an action, or records it with the target, size and plan entry it would have had in a dry run. These
+    /// are only worked out for a dry run
+    pub fn execute<T, E>(
+        &self,
+        operation: OPERATION,
+        target: impl FnOnce() -> String,
+        size: impl FnOnce() -> u64,
+        entry: impl FnOnce() -> Option<PlanEntry>,
+        action: impl FnOnce() -> Result<T, E>,
+    ) -> Result<Option<T>, E> {
+        if !self.dry_run {
+            return action().map(Some);
+        }
+        self.planned.lock().unwrap().push(PlannedAction {
+            operation,
+            target: target(),
+            size: size(),
+            entry: entry(),
+        });
+        Ok(None)
+    }
+
+    ///Finishes the output of an operation. A real run says how many files were processed, while a dry run lists
+    /// everything that would have been done
+    pub fn finish(&self, pb: &OpSpinner, n: usize) {
+        if self.dry_run {
+            pb.finish();
+            self.print_dry_run();
+        } else {
+            pb.auto_finish(n);
+        }
+    }
+
+    ///Lists everything that would have been done if this is a dry run, and writes it out if a plan was asked for
+    pub fn print_dry_run(&self) {
+        if !self.dry_run {
+            return;
+        }
+        let planned = self.planned.lock().unwrap();
+        output::print_dry_run(&planned);
+
+        if let Some(path) = &self.plan_out {
+            let plan = Plan {
+                args: wild::args().collect(),
+                cwd: env::current_dir().unwrap_or_default(),
+                created: chrono::Utc::now(),
+                entries: planned.iter().filter_map(|a| a.entry.clone()).collect(),
+            };
+            output::print_plan_written(path, plan.entries.len(), plan.write(path));
+        }
+    }
+}
+
+//Directories aren't counted towards the size, since everything inside them is listed separately
+fn path_size(path: &Path) -> u64 {
+    match fs::symlink_metadata(path) {
+        Ok(m) if m.is_file() => m.len(),
+        _ => 0,
+    }
+}
+
+fn trash_item_size(item: &TrashItem) -> u64 {
+    let options = WalkOptions {
+        recurse: true,
+        protection: Protection::none(),
+        ..Default::default()
+    };
+    files::trash_item_backing_path(item)
+        .map_or(0, |path| files::total_file_size(&[&path], &options))
+}
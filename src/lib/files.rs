@@ -1,13 +1,27 @@
 use std::{
     borrow::Cow,
+    error::Error,
     ffi::OsStr,
-    fs::{self, File},
-    io::{self, BufWriter, Seek, Write},
+    fmt::Display,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek},
+    ops::{Deref, DerefMut},
     path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
+
+use rand::RngCore;
 use trash::{os_limited, TrashItem};
 
-use crate::{FileErr, RecursiveCallback};
+use crate::{
+    filter::{EntryType, Filter},
+    protect::{self, ProtectedPath, Protection},
+    throttle::TokenBucket,
+    FileErr, RecursiveCallback,
+};
 
 ///Returns a losslessly converted string if possible, but if that errors return the lossy conversion.
 //This function is used pretty much everywhere. While it may cause issues in some edge case,
@@ -20,43 +34,396 @@ pub fn path_to_string<P: AsRef<Path>>(path: P) -> String {
 }
 
 //Same as above for os_str
-pub fn os_str_to_str<'a>(path: &'a OsStr) -> Cow<'_, str> {
+pub fn os_str_to_str(path: &OsStr) -> Cow<'_, str> {
     match path.to_str() {
         Some(s) => Cow::Borrowed(s),
         None => path.to_string_lossy(),
     }
 }
 
+///How the walkers treat symbolic links to directories. By default links are never followed, so a link inside a
+/// tree can't lead a recursive operation out of it
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SymlinkPolicy {
+    ///Links are operated on as links, like -P in GNU tools
+    #[default]
+    Never,
+    ///Links given as paths to operate on are followed, but not ones found while recursing, like -H
+    CommandLine,
+    ///Every link to a directory is followed, like -L
+    Always,
+}
+
+impl SymlinkPolicy {
+    ///Whether a link should be followed. Top level paths are the ones the operation was given
+    pub fn follows(self, top_level: bool) -> bool {
+        match self {
+            SymlinkPolicy::Never => false,
+            SymlinkPolicy::CommandLine => top_level,
+            SymlinkPolicy::Always => true,
+        }
+    }
+}
+
+///Everything controlling how the walkers go over the paths they're given
+#[derive(Clone, Debug, Default)]
+pub struct WalkOptions {
+    ///Whether directories are descended into. Otherwise they're passed to the callbacks like anything else
+    pub recurse: bool,
+    pub symlinks: SymlinkPolicy,
+    ///Don't descend into directories on a different filesystem to the path being walked. The mount points are
+    /// passed to the mount_skipped callback instead, and the directories containing them are left in place
+    pub one_file_system: bool,
+    ///Paths the walk refuses to touch, which are the builtin ones unless set otherwise. The paths given to it are
+    /// all checked before anything is done, and anything protected inside the tree is passed to the
+    /// protected_skipped callback instead
+    pub protection: Protection,
+    ///Narrows down which entries the callbacks are run on
+    pub filter: Filter,
+}
+
+///Returns whether a path given to an operation is a directory which should be descended into
+pub fn is_walkable_dir(path: &Path, options: &WalkOptions) -> bool {
+    options.recurse
+        && match options.symlinks.follows(true) {
+            true => fs::metadata(path),
+            false => fs::symlink_metadata(path),
+        }
+        .is_ok_and(|m| m.is_dir())
+}
+
+//What the walkers keep track of while walking down from one of the paths they were given
+#[derive(Clone)]
+pub(crate) struct WalkState {
+    //Directories being walked, from the top level path down. Only kept when links are followed below the top
+    //level, since following a link back to one of them would otherwise walk the same tree forever
+    ancestors: Vec<PathBuf>,
+    //Device of the top level path. Only kept when the walk has to stay on one filesystem
+    device: Option<u64>,
+    //Depth of the entries in the directory being walked, where the top level path is at 0
+    pub(crate) depth: usize,
+}
+
+impl WalkState {
+    pub(crate) fn new(root: &Path, options: &WalkOptions) -> WalkState {
+        WalkState {
+            ancestors: Vec::new(),
+            device: match options.one_file_system {
+                true => fs::metadata(root).ok().and_then(|m| device_of(&m)),
+                false => None,
+            },
+            depth: 0,
+        }
+    }
+
+    pub(crate) fn enter(&mut self, dir: &Path, options: &WalkOptions) {
+        if options.symlinks.follows(false) {
+            self.ancestors
+                .push(fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf()));
+        }
+        self.depth += 1;
+    }
+
+    fn leave(&mut self) {
+        self.ancestors.pop();
+        self.depth -= 1;
+    }
+
+    fn is_ancestor(&self, dir: &Path) -> bool {
+        !self.ancestors.is_empty()
+            && fs::canonicalize(dir).is_ok_and(|d| self.ancestors.contains(&d))
+    }
+
+    //Takes the metadata of the directory if it's already been looked up, as it has for followed links
+    fn crosses_device(&self, dir: &Path, metadata: Option<fs::Metadata>) -> bool {
+        let Some(device) = self.device else {
+            return false;
+        };
+        metadata
+            .or_else(|| fs::symlink_metadata(dir).ok())
+            .and_then(|m| device_of(&m))
+            .is_some_and(|d| d != device)
+    }
+}
+
+#[cfg(unix)]
+fn device_of(metadata: &fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.dev())
+}
+
+//There's no stable way to get the volume of a file elsewhere, so the walk can't tell when it crosses one
+#[cfg(not(unix))]
+fn device_of(_metadata: &fs::Metadata) -> Option<u64> {
+    None
+}
+
+pub(crate) enum EntryKind {
+    Dir,
+    Other,
+    //A directory which is already being walked, reached again through a followed link
+    Visited,
+    //A directory on another filesystem, which a walk staying on one filesystem doesn't go into
+    Mount,
+    Protected(ProtectedPath),
+    //Left out by the filters without being looked at
+    Pruned,
+}
+
+//Anything marked as protected is skipped like anything matching a pattern, whether it's walked or not
+pub(crate) fn entry_kind(
+    path: &Path,
+    file_type: fs::FileType,
+    options: &WalkOptions,
+    state: &WalkState,
+) -> EntryKind {
+    match unmarked_entry_kind(path, file_type, options, state) {
+        kind @ (EntryKind::Dir | EntryKind::Other) => {
+            match options
+                .protection
+                .check_marked_entry(path, || protect::is_marked(path))
+            {
+                Ok(()) => kind,
+                Err(protected) => EntryKind::Protected(protected),
+            }
+        }
+        kind => kind,
+    }
+}
+
+//Uses the type from the directory entry, so nothing is followed and no extra stat is needed unless links are or
+//the walk has to stay on one filesystem. Links to directories which are already being walked are treated as
+//plain links
+fn unmarked_entry_kind(
+    path: &Path,
+    file_type: fs::FileType,
+    options: &WalkOptions,
+    state: &WalkState,
+) -> EntryKind {
+    if let Err(protected) = options.protection.check_entry(path) {
+        return EntryKind::Protected(protected);
+    }
+    if options.filter.prunes(path, state.depth) {
+        return EntryKind::Pruned;
+    }
+    let metadata = if file_type.is_symlink() && options.symlinks.follows(false) {
+        match fs::metadata(path) {
+            Ok(m) if m.is_dir() && !state.is_ancestor(path) => {
+                if let Err(protected) = options.protection.check_followed(path) {
+                    return EntryKind::Protected(protected);
+                }
+                Some(m)
+            }
+            _ => return EntryKind::Other,
+        }
+    } else if file_type.is_dir() {
+        if state.is_ancestor(path) {
+            return EntryKind::Visited;
+        }
+        None
+    } else {
+        return EntryKind::Other;
+    };
+
+    match state.crosses_device(path, metadata) {
+        true => EntryKind::Mount,
+        false => EntryKind::Dir,
+    }
+}
+
+pub(crate) fn entry_type(file_type: fs::FileType) -> EntryType {
+    if file_type.is_dir() {
+        EntryType::Dir
+    } else if file_type.is_symlink() {
+        EntryType::Symlink
+    } else if file_type.is_file() {
+        EntryType::File
+    } else {
+        EntryType::Other
+    }
+}
+
+fn file_times(metadata: &fs::Metadata) -> Option<(u64, std::time::SystemTime)> {
+    Some((metadata.len(), metadata.modified().ok()?))
+}
+
+//Whether the filters select an entry found while walking, at the depth of the directory being walked
+pub(crate) fn selects_entry(
+    path: &Path,
+    file_type: fs::FileType,
+    options: &WalkOptions,
+    state: &WalkState,
+) -> bool {
+    options
+        .filter
+        .selects(path, state.depth, entry_type(file_type), || {
+            fs::symlink_metadata(path).ok().and_then(|m| file_times(&m))
+        })
+}
+
+///Returns whether the filters select one of the paths given to a walk
+pub(crate) fn selects_top_level(path: &Path, options: &WalkOptions) -> bool {
+    match fs::symlink_metadata(path) {
+        Ok(m) => options
+            .filter
+            .selects(path, 0, entry_type(m.file_type()), || file_times(&m)),
+        Err(_) => options.filter.selects(path, 0, EntryType::Other, || None),
+    }
+}
+
+#[derive(PartialEq)]
+enum Walked {
+    Complete,
+    //Something inside the directory was skipped, so the directory itself can't be processed either
+    Partial,
+    //The callbacks stopped the traversal
+    Stopped,
+}
+
+///Runs the callbacks of an operation on everything inside a directory, and then the directory itself.
+/// Returns the number of paths the callbacks succeeded on, and false if the callbacks stopped the traversal
 pub fn run_op_on_dir_recursive<T>(
     operation: &mut T,
     dir: &Path,
     mut count: usize,
+    options: &WalkOptions,
 ) -> Result<(usize, bool), FileErr>
 where
     T: RecursiveCallback,
 {
-    if dir.is_dir() {
-        for entry in fs::read_dir(dir).map_err(|e| FileErr::map(e, dir))? {
-            let entry = entry.map_err(|e| FileErr::map(e, dir))?;
-            let path = entry.path();
-            if path.is_dir() {
-                if !run_op_on_dir_recursive(operation, &path, count)?.1 {
-                    return Ok((count, false));
+    let mut state = WalkState::new(dir, options);
+    let file_type = fs::symlink_metadata(dir)
+        .map_err(|e| FileErr::map(e, dir))?
+        .file_type();
+    let walked = run_op_on_dir(operation, dir, file_type, &mut count, options, &mut state)?;
+    Ok((count, walked != Walked::Stopped))
+}
+
+fn run_op_on_dir<T>(
+    operation: &mut T,
+    dir: &Path,
+    file_type: fs::FileType,
+    count: &mut usize,
+    options: &WalkOptions,
+    state: &mut WalkState,
+) -> Result<Walked, FileErr>
+where
+    T: RecursiveCallback,
+{
+    state.enter(dir, options);
+    let walked = match fs::read_dir(dir) {
+        Ok(entries) => run_op_on_entries(operation, dir, entries, count, options, state),
+        Err(e) => skip_failed(operation, FileErr::map(e, dir)),
+    };
+    state.leave();
+
+    let walked = walked?;
+    if walked != Walked::Complete {
+        return Ok(walked);
+    }
+    if !selects_entry(dir, file_type, options, state) {
+        return Ok(Walked::Partial);
+    }
+    match operation.execute_callbacks(dir, true) {
+        Ok(true) => {
+            *count += 1;
+            Ok(Walked::Complete)
+        }
+        Ok(false) => {
+            *count += 1;
+            Ok(Walked::Stopped)
+        }
+        Err(e) => skip_failed(operation, e),
+    }
+}
+
+fn run_op_on_entries<T>(
+    operation: &mut T,
+    dir: &Path,
+    entries: fs::ReadDir,
+    count: &mut usize,
+    options: &WalkOptions,
+    state: &mut WalkState,
+) -> Result<Walked, FileErr>
+where
+    T: RecursiveCallback,
+{
+    let mut walked = Walked::Complete;
+
+    for entry in entries {
+        let (path, file_type) = match entry.and_then(|e| Ok((e.path(), e.file_type()?))) {
+            Ok(e) => e,
+            Err(e) => {
+                walked = skip_failed(operation, FileErr::map(e, dir))?;
+                continue;
+            }
+        };
+        let entry_walked = match entry_kind(&path, file_type, options, state) {
+            EntryKind::Dir => run_op_on_dir(operation, &path, file_type, count, options, state)?,
+            EntryKind::Other if !selects_entry(&path, file_type, options, state) => Walked::Partial,
+            EntryKind::Other => match operation.execute_callbacks(&path, false) {
+                Ok(true) => {
+                    *count += 1;
+                    Walked::Complete
                 }
-            } else {
-                count += 1;
-                if !operation.execute_callbacks(&path, false)? {
-                    return Ok((count, false));
+                Ok(false) => {
+                    *count += 1;
+                    Walked::Stopped
                 }
+                Err(e) => skip_failed(operation, e)?,
+            },
+            EntryKind::Visited => Walked::Complete,
+            EntryKind::Mount => {
+                operation.mount_skipped(&path);
+                Walked::Partial
+            }
+            EntryKind::Protected(protected) => {
+                operation.protected_skipped(&protected);
+                Walked::Partial
             }
+            EntryKind::Pruned => Walked::Partial,
+        };
+        match entry_walked {
+            Walked::Complete => {}
+            Walked::Partial => walked = Walked::Partial,
+            Walked::Stopped => return Ok(Walked::Stopped),
+        }
+    }
+
+    Ok(walked)
+}
+
+///Checks the paths given to a walk against the protected paths before anything is done to any of them, and
+/// whether they're marked or contain marked paths. Protected paths are passed to the failed callback, and left
+/// out of the walk if it carries on past them
+pub(crate) fn unprotected_paths<'a>(
+    paths: Vec<&'a Path>,
+    options: &WalkOptions,
+    mut failed: impl FnMut(FileErr) -> Result<(), FileErr>,
+) -> Result<Vec<&'a Path>, FileErr> {
+    let follow = options.recurse && options.symlinks.follows(true);
+    let mut unprotected = Vec::with_capacity(paths.len());
+    for path in paths {
+        let checked = options
+            .protection
+            .check(path, follow)
+            .and_then(|_| options.protection.check_marked(path))
+            .and_then(|_| options.protection.check_walk(path, options));
+        match checked {
+            Ok(()) => unprotected.push(path),
+            Err(e) => failed(FileErr::map(
+                io::Error::new(io::ErrorKind::PermissionDenied, e),
+                path,
+            ))?,
         }
-        count += 1;
-        return Ok((
-            count,
-            operation.execute_callbacks(&PathBuf::from(dir), true)?,
-        ));
     }
-    Ok((count, true))
+    Ok(unprotected)
+}
+
+//Passes an error to the operation, which either stops the walk with it or has the failed file skipped
+fn skip_failed<T: RecursiveCallback>(operation: &mut T, err: FileErr) -> Result<Walked, FileErr> {
+    operation.failed(err)?;
+    Ok(Walked::Partial)
 }
 
 pub fn select_from_trash(name: &String) -> Option<Vec<TrashItem>> {
@@ -75,7 +442,7 @@ pub fn select_from_trash(name: &String) -> Option<Vec<TrashItem>> {
 }
 
 pub fn get_existent_trash_items(
-    names: &Vec<String>,
+    names: &[String],
     s_cb: impl Fn(Vec<TrashItem>) -> TrashItem,
     d_cb: impl Fn(&String),
 ) -> Vec<TrashItem> {
@@ -91,43 +458,783 @@ pub fn get_existent_trash_items(
         .collect()
 }
 
-pub fn overwrite_file(file: &File, runs: usize) -> std::io::Result<()> {
-    const OW_BUFF_SIZE: usize = 10usize.pow(6);
-    let file_len = file.metadata()?.len();
+///Options controlling how overwrite_file writes over the contents of a file
+#[derive(Clone, Debug)]
+pub struct OverwriteOptions {
+    pub runs: usize,
+    ///Bypass the page cache using O_DIRECT. The file must have been opened with open_for_overwrite
+    pub direct_io: bool,
+    ///Read the file back after the final pass and check that it was actually overwritten
+    pub verify: bool,
+    ///Also overwrite the slack space between the end of the file and the end of its last block
+    pub slack: bool,
+    pub pattern: OverwritePattern,
+    ///Size of each write. Rounded up to a multiple of the O_DIRECT alignment
+    pub buffer_size: usize,
+    pub backend: WriteBackend,
+    ///Limits how fast the overwrite writes. Share the same bucket to put a single limit on several overwrites
+    pub bwlimit: Option<Arc<TokenBucket>>,
+    ///Pass to start from, for picking an interrupted overwrite back up. Passes are numbered from 1
+    pub first_pass: usize,
+    ///Setting this stops the overwrite after the write in progress. What was written is synced, and the
+    /// overwrite fails with an OverwriteInterrupted error
+    pub cancel: Option<Arc<AtomicBool>>,
+}
+
+impl Default for OverwriteOptions {
+    fn default() -> Self {
+        OverwriteOptions {
+            runs: 1,
+            direct_io: false,
+            verify: false,
+            slack: false,
+            pattern: OverwritePattern::default(),
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            backend: WriteBackend::default(),
+            bwlimit: None,
+            first_pass: 1,
+            cancel: None,
+        }
+    }
+}
+
+///What gets written over the contents of a file on each pass
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OverwritePattern {
+    #[default]
+    Zeroes,
+    Ones,
+    ///A fresh block of random data for every pass, repeated over the whole file
+    Random,
+}
+
+impl OverwritePattern {
+    pub fn name(self) -> &'static str {
+        match self {
+            OverwritePattern::Zeroes => "zeroes",
+            OverwritePattern::Ones => "ones",
+            OverwritePattern::Random => "random",
+        }
+    }
+}
+
+///How writes are submitted to the kernel
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum WriteBackend {
+    ///One positional write at a time
+    #[default]
+    Positional,
+    ///Several writes in flight at once through io_uring. Requires the io-uring feature and Linux
+    IoUring,
+}
+
+pub const DEFAULT_BUFFER_SIZE: usize = 1 << 20;
+//O_DIRECT requires the buffer address, offset and length to be aligned to the logical block size.
+//4KiB covers every block size we're realistically going to run into
+const DIRECT_ALIGN: usize = 4096;
+
+///Opens a file for writing in the mode required by the given OverwriteOptions.
+/// On Linux symlinks are never followed, and opening a FIFO won't block waiting for a reader
+pub fn open_for_overwrite<P: AsRef<Path>>(path: P, opts: &OverwriteOptions) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).read(true);
+
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
 
+        let mut flags = libc::O_NOFOLLOW | libc::O_NONBLOCK;
+        if opts.direct_io {
+            flags |= libc::O_DIRECT;
+        }
+        options.custom_flags(flags);
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    if opts.direct_io {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "direct I/O is only supported on Linux",
+        ));
+    }
+
+    options.open(path)
+}
+
+///Overwrites the contents of a file with the pattern from the options. Every pass is synced to disk before the next one starts,
+/// otherwise the page cache is free to collapse all of them into a single write of the final pass
+pub fn overwrite_file(file: &File, opts: &OverwriteOptions) -> io::Result<()> {
+    overwrite_file_observed(file, opts, &mut ())
+}
+
+///Receives progress updates from overwrite_file_observed
+pub trait OverwriteObserver {
+    ///Called once before anything is written to a file
+    fn overwrite_started(&mut self, _file_len: u64, _runs: usize) {}
+    ///Called before each pass over the file starts. Passes are numbered from 1
+    fn pass_started(&mut self, _pass: usize, _runs: usize) {}
+    ///Called after every write with the number of bytes that were just written
+    fn bytes_written(&mut self, bytes: u64);
+}
+
+impl OverwriteObserver for () {
+    fn bytes_written(&mut self, _bytes: u64) {}
+}
+
+///Same as overwrite_file, but reports its progress to the given observer
+pub fn overwrite_file_observed(
+    file: &File,
+    opts: &OverwriteOptions,
+    observer: &mut dyn OverwriteObserver,
+) -> io::Result<()> {
     if file.metadata()?.is_dir() {
         return Ok(());
     }
 
-    let mut writer = BufWriter::new(file);
+    //If something else changes the size of the file while we're overwriting it, parts of it could have been
+    //missed. The whole thing gets overwritten once more before giving up on it
+    let mut size_change = None;
+    for _ in 0..2 {
+        size_change = overwrite_passes(file, opts, observer)?;
+        if size_change.is_none() {
+            return Ok(());
+        }
+    }
 
-    let buf = vec![0u8; OW_BUFF_SIZE];
+    let (expected, actual) = size_change.unwrap();
+    Err(io::Error::other(format!(
+        "the file changed size from {expected} to {actual} bytes while it was being overwritten"
+    )))
+}
 
-    for _ in 0..runs {
-        writer.seek(io::SeekFrom::Start(0))?;
+///Runs every pass of an overwrite. Returns the expected and actual size of the file if it changed part way through
+fn overwrite_passes(
+    file: &File,
+    opts: &OverwriteOptions,
+    observer: &mut dyn OverwriteObserver,
+) -> io::Result<Option<(u64, u64)>> {
+    let metadata = file.metadata()?;
+    let file_len = writable_len(file, &metadata)?;
 
-        //Keep track of our position in the file ourselves based on the delusion that it might impact
-        //performace to seek on each loop iteration
-        loop {
-            let offset = writer.seek(io::SeekFrom::Current(0)).unwrap();
-            if (file_len - offset) >= OW_BUFF_SIZE.try_into().unwrap() {
-                writer.write_all(&buf)?;
-            } else {
-                writer.write_all(&vec![0u8; (file_len - offset).try_into().unwrap()])?;
-                break;
+    //Only the allocated parts of sparse files are overwritten, writing to the holes would just allocate new blocks
+    let mut extents = data_extents(file, file_len)?;
+    if opts.slack && metadata.is_file() {
+        extend_to_slack(&mut extents, &metadata, file_len);
+    }
+    let written_len = extents.last().map_or(0, |e| e.1).max(file_len);
+    let data_len = extents.iter().map(|(start, end)| end - start).sum();
+
+    let mut buf = PatternBuffer::new(opts);
+
+    observer.overwrite_started(data_len, opts.runs);
+    for pass in opts.first_pass.max(1)..=opts.runs {
+        observer.pass_started(pass, opts.runs);
+        buf.refill();
+        for &(start, end) in &extents {
+            if let Err(e) = write_pattern(file, start, end, &buf, opts, observer) {
+                if !is_cancelled(opts) {
+                    return Err(e);
+                }
+                file.sync_data()?;
+                return Err(io::Error::new(
+                    io::ErrorKind::Interrupted,
+                    OverwriteInterrupted { pass },
+                ));
+            }
+        }
+
+        file.sync_data()?;
+
+        let current_len = writable_len(file, &file.metadata()?)?;
+        if current_len != written_len {
+            return Ok(Some((written_len, current_len)));
+        }
+    }
+
+    if opts.verify {
+        #[cfg(target_os = "linux")]
+        if opts.direct_io {
+            set_direct_flag(file, false)?;
+        }
+        verify_overwrite(file, &extents, &buf)?;
+        #[cfg(target_os = "linux")]
+        if opts.direct_io {
+            set_direct_flag(file, true)?;
+        }
+    }
+
+    //Writing the slack grows the file, so put it back to the size it was
+    if written_len > file_len {
+        file.set_len(file_len)?;
+        file.sync_all()?;
+    }
+
+    Ok(None)
+}
+
+///Error payload of an overwrite that was stopped through its cancel flag
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OverwriteInterrupted {
+    ///The pass that was in progress, which has to be run again to finish the overwrite
+    pub pass: usize,
+}
+
+impl Display for OverwriteInterrupted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "interrupted during pass {}", self.pass)
+    }
+}
+
+impl Error for OverwriteInterrupted {}
+
+///Returns the pass an overwrite was interrupted during, if the error is from an interrupted overwrite
+pub fn interrupted_pass(err: &io::Error) -> Option<usize> {
+    err.get_ref()
+        .and_then(|e| e.downcast_ref::<OverwriteInterrupted>())
+        .map(|i| i.pass)
+}
+
+fn is_cancelled(opts: &OverwriteOptions) -> bool {
+    opts.cancel
+        .as_ref()
+        .is_some_and(|c| c.load(Ordering::Relaxed))
+}
+
+fn cancelled_error() -> io::Error {
+    io::Error::new(io::ErrorKind::Interrupted, "the overwrite was cancelled")
+}
+
+///Extends the final extent of a file to the end of the last block allocated to it, so that the slack space between
+/// the end of the file and the end of the block gets overwritten too
+fn extend_to_slack(extents: &mut [(u64, u64)], metadata: &fs::Metadata, file_len: u64) {
+    #[cfg(unix)]
+    let (block_size, allocated) = {
+        use std::os::unix::fs::MetadataExt;
+        (metadata.blksize().max(1), metadata.blocks() * 512)
+    };
+    //Without block information the best we can do is assume the most common block size
+    #[cfg(not(unix))]
+    let (block_size, allocated) = {
+        let _ = metadata;
+        (DIRECT_ALIGN as u64, 0)
+    };
+
+    let rounded_len = file_len.div_ceil(block_size) * block_size;
+    //Blocks can be preallocated past the end of the file, but sparse files have less allocated than their length
+    let slack_end = match allocated > rounded_len {
+        true => allocated.div_ceil(block_size) * block_size,
+        false => rounded_len,
+    };
+
+    if let Some(last) = extents.last_mut() {
+        if last.1 == file_len {
+            last.1 = slack_end;
+        }
+    }
+}
+
+///Block devices report a length of zero, so their size has to be found by seeking to the end
+fn writable_len(mut file: &File, metadata: &fs::Metadata) -> io::Result<u64> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        if metadata.file_type().is_block_device() {
+            return file.seek(io::SeekFrom::End(0));
+        }
+    }
+
+    Ok(metadata.len())
+}
+
+///Returns the start and end offsets of the parts of a file which actually have storage allocated to them
+#[cfg(target_os = "linux")]
+pub fn data_extents(file: &File, file_len: u64) -> io::Result<Vec<(u64, u64)>> {
+    use std::os::fd::AsRawFd;
+
+    let fd = file.as_raw_fd();
+    let mut extents = Vec::new();
+    let mut offset: u64 = 0;
+
+    while offset < file_len {
+        let start = unsafe { libc::lseek(fd, offset as libc::off_t, libc::SEEK_DATA) };
+        if start < 0 {
+            let err = io::Error::last_os_error();
+            return match err.raw_os_error() {
+                //There's no data left past the offset
+                Some(libc::ENXIO) => Ok(extents),
+                //The filesystem can't tell us, so treat the whole file as data
+                Some(libc::EINVAL) | Some(libc::EOPNOTSUPP) => Ok(vec![(0, file_len)]),
+                _ => Err(err),
+            };
+        }
+
+        let end = unsafe { libc::lseek(fd, start, libc::SEEK_HOLE) };
+        if end < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let end = (end as u64).min(file_len);
+        extents.push((start as u64, end));
+        offset = end;
+    }
+
+    Ok(extents)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn data_extents(_file: &File, file_len: u64) -> io::Result<Vec<(u64, u64)>> {
+    Ok(match file_len {
+        0 => vec![],
+        len => vec![(0, len)],
+    })
+}
+
+///Deallocates the whole of a file with FALLOC_FL_PUNCH_HOLE while keeping its size, so the filesystem can discard
+/// the blocks on the underlying device. Returns false if the filesystem doesn't support punching holes
+#[cfg(target_os = "linux")]
+pub fn punch_holes(file: &File) -> io::Result<bool> {
+    use std::os::fd::AsRawFd;
+
+    let len = file.metadata()?.len();
+    if len == 0 {
+        return Ok(true);
+    }
+
+    let mode = libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE;
+    if unsafe { libc::fallocate(file.as_raw_fd(), mode, 0, len as libc::off_t) } < 0 {
+        let err = io::Error::last_os_error();
+        return match err.raw_os_error() {
+            Some(libc::EOPNOTSUPP) | Some(libc::ENOSYS) => Ok(false),
+            _ => Err(err),
+        };
+    }
+
+    file.sync_all()?;
+    Ok(true)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn punch_holes(_file: &File) -> io::Result<bool> {
+    Ok(false)
+}
+
+///Reads the given extents of a file back and returns an InvalidData error if any of them don't hold the pattern
+/// from the last pass
+fn verify_overwrite(
+    mut file: &File,
+    extents: &[(u64, u64)],
+    pattern: &PatternBuffer,
+) -> io::Result<()> {
+    //Drop the cached pages first so we actually read back what made it to the disk
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::fd::AsRawFd;
+        unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) };
+    }
+
+    let mut buf = vec![0u8; pattern.len()];
+
+    for &(start, end) in extents {
+        let mut offset = start;
+        file.seek(io::SeekFrom::Start(start))?;
+
+        while offset < end {
+            let len = (end - offset).min(buf.len() as u64) as usize;
+            file.read_exact(&mut buf[..len])?;
+            if buf[..len] != pattern[..len] {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "verification failed, the file was not fully overwritten",
+                ));
+            }
+            offset += len as u64;
+        }
+    }
+
+    Ok(())
+}
+
+///Buffer holding the pattern of a pass, allocated once and reused for every write of an overwrite.
+/// It's aligned so it can be used for O_DIRECT writes
+pub struct PatternBuffer {
+    buf: AlignedBuffer,
+    pattern: OverwritePattern,
+}
+
+impl PatternBuffer {
+    pub fn new(opts: &OverwriteOptions) -> PatternBuffer {
+        let len = opts.buffer_size.max(1).div_ceil(DIRECT_ALIGN) * DIRECT_ALIGN;
+        let mut buf = PatternBuffer {
+            buf: AlignedBuffer::zeroed(len, DIRECT_ALIGN),
+            pattern: opts.pattern,
+        };
+        buf.refill();
+        buf
+    }
+
+    ///Fills the buffer with the pattern. Only random patterns change between passes
+    pub fn refill(&mut self) {
+        match self.pattern {
+            OverwritePattern::Zeroes => self.buf.fill(0),
+            OverwritePattern::Ones => self.buf.fill(0xFF),
+            OverwritePattern::Random => rand::thread_rng().fill_bytes(&mut self.buf),
+        }
+    }
+}
+
+impl Deref for PatternBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+///Writes the pattern over a range of a file, growing the file if the range goes past its end.
+/// The pattern repeats from the start of the range, so the byte at offset o is buf[(o - start) % buf.len()].
+/// Does not sync anything to disk
+pub fn write_pattern(
+    file: &File,
+    start: u64,
+    end: u64,
+    buf: &PatternBuffer,
+    opts: &OverwriteOptions,
+    observer: &mut dyn OverwriteObserver,
+) -> io::Result<()> {
+    //O_DIRECT writes have to be a whole number of blocks, so an unaligned tail goes through the page cache
+    let aligned_end = match opts.direct_io {
+        true => end - ((end - start) % DIRECT_ALIGN as u64),
+        false => end,
+    };
+
+    match opts.backend {
+        WriteBackend::Positional => write_range(file, start, aligned_end, buf, opts, observer)?,
+        WriteBackend::IoUring => write_range_uring(file, start, aligned_end, buf, opts, observer)?,
+    }
+
+    if aligned_end < end {
+        let tail_start = ((aligned_end - start) % buf.len() as u64) as usize;
+        let tail = &buf[tail_start..tail_start + (end - aligned_end) as usize];
+        if is_cancelled(opts) {
+            return Err(cancelled_error());
+        }
+        if let Some(limit) = &opts.bwlimit {
+            limit.acquire(tail.len() as u64);
+        }
+
+        #[cfg(target_os = "linux")]
+        set_direct_flag(file, false)?;
+        let res = write_all_at(file, tail, aligned_end);
+        #[cfg(target_os = "linux")]
+        set_direct_flag(file, true)?;
+        res?;
+        observer.bytes_written(tail.len() as u64);
+    }
+
+    Ok(())
+}
+
+fn write_range(
+    file: &File,
+    start: u64,
+    end: u64,
+    buf: &[u8],
+    opts: &OverwriteOptions,
+    observer: &mut dyn OverwriteObserver,
+) -> io::Result<()> {
+    let mut offset = start;
+    while offset < end {
+        if is_cancelled(opts) {
+            return Err(cancelled_error());
+        }
+        let len = (end - offset).min(buf.len() as u64) as usize;
+        if let Some(limit) = &opts.bwlimit {
+            limit.acquire(len as u64);
+        }
+        write_all_at(file, &buf[..len], offset)?;
+        observer.bytes_written(len as u64);
+        offset += len as u64;
+    }
+    Ok(())
+}
+
+//Keeps this many writes queued, which is enough to keep a fast SSD busy without tying up much memory.
+//Every write reads from the same buffer, so nothing extra needs allocating for them
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+const URING_QUEUE_DEPTH: usize = 8;
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+fn write_range_uring(
+    file: &File,
+    start: u64,
+    end: u64,
+    buf: &[u8],
+    opts: &OverwriteOptions,
+    observer: &mut dyn OverwriteObserver,
+) -> io::Result<()> {
+    use io_uring::{opcode, types, IoUring};
+    use std::os::fd::AsRawFd;
+
+    let mut ring = IoUring::new(URING_QUEUE_DEPTH as u32)?;
+    let fd = types::Fd(file.as_raw_fd());
+    //Offset and length of every write, indexed by the user data of its submission
+    let mut writes: Vec<(u64, usize)> = Vec::new();
+    let mut offset = start;
+    let mut in_flight = 0;
+
+    while offset < end || in_flight > 0 {
+        //Nothing new is submitted once cancelled, but whatever is in flight still has to complete
+        let cancelled = is_cancelled(opts);
+        if cancelled && in_flight == 0 {
+            return Err(cancelled_error());
+        }
+
+        while offset < end && in_flight < URING_QUEUE_DEPTH && !cancelled {
+            let len = (end - offset).min(buf.len() as u64) as usize;
+            if let Some(limit) = &opts.bwlimit {
+                limit.acquire(len as u64);
             }
+            let entry = opcode::Write::new(fd, buf.as_ptr(), len as u32)
+                .offset(offset)
+                .build()
+                .user_data(writes.len() as u64);
+            //The buffer outlives the ring, which is drained before returning
+            unsafe { ring.submission().push(&entry) }.map_err(io::Error::other)?;
+            writes.push((offset, len));
+            offset += len as u64;
+            in_flight += 1;
+        }
+
+        ring.submit_and_wait(1)?;
+        let completed: Vec<(u64, i32)> = ring
+            .completion()
+            .map(|c| (c.user_data(), c.result()))
+            .collect();
+        //Everything taken off the completion queue is done with, whether or not it gets looked at below
+        in_flight -= completed.len();
+
+        let mut failed = None;
+        for (index, result) in completed {
+            let (write_offset, len) = writes[index as usize];
+            let res = match result {
+                r if r < 0 => Err(io::Error::from_raw_os_error(-r)),
+                //Short writes are rare, finishing them off synchronously keeps the bookkeeping simple
+                r if (r as usize) < len => {
+                    finish_short_write(file, &buf[..len], write_offset, r as usize, opts.direct_io)
+                }
+                _ => Ok(()),
+            };
+            match res {
+                Ok(()) => observer.bytes_written(len as u64),
+                Err(e) => {
+                    failed = Some(e);
+                    break;
+                }
+            }
+        }
+
+        if let Some(e) = failed {
+            //Wait for the rest so the kernel is done with the buffer before it can be dropped
+            while in_flight > 0 {
+                ring.submit_and_wait(in_flight)?;
+                in_flight -= ring.completion().count();
+            }
+            return Err(e);
         }
     }
 
     Ok(())
 }
 
-pub fn remove_file_or_empty_dir(path: &PathBuf) -> std::io::Result<()> {
-    if !path.exists() {
-        return Err(std::io::ErrorKind::NotFound.into());
+///Writes the rest of a write which came up short. O_DIRECT writes have to start on a block boundary, so the rest
+/// is written from the last one it got past, writing the same bytes over again up to where it stopped
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+fn finish_short_write(
+    file: &File,
+    buf: &[u8],
+    offset: u64,
+    written: usize,
+    direct_io: bool,
+) -> io::Result<()> {
+    let from = match direct_io {
+        true => written - written % DIRECT_ALIGN,
+        false => written,
+    };
+    write_all_at(file, &buf[from..], offset + from as u64)
+}
+
+#[cfg(not(all(target_os = "linux", feature = "io-uring")))]
+fn write_range_uring(
+    _file: &File,
+    _start: u64,
+    _end: u64,
+    _buf: &[u8],
+    _opts: &OverwriteOptions,
+    _observer: &mut dyn OverwriteObserver,
+) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "the io_uring backend requires Linux and the io-uring feature",
+    ))
+}
+
+#[cfg(unix)]
+fn write_all_at(file: &File, buf: &[u8], offset: u64) -> io::Result<()> {
+    use std::os::unix::fs::FileExt;
+    file.write_all_at(buf, offset)
+}
+
+#[cfg(windows)]
+fn write_all_at(file: &File, mut buf: &[u8], mut offset: u64) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buf.is_empty() {
+        match file.seek_write(buf, offset) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => {
+                buf = &buf[n..];
+                offset += n as u64;
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn set_direct_flag(file: &File, enabled: bool) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let fd = file.as_raw_fd();
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let new_flags = match enabled {
+        true => flags | libc::O_DIRECT,
+        false => flags & !libc::O_DIRECT,
+    };
+    if unsafe { libc::fcntl(fd, libc::F_SETFL, new_flags) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+///A zeroed buffer whose start is aligned to the given alignment
+struct AlignedBuffer {
+    storage: Vec<u8>,
+    offset: usize,
+    len: usize,
+}
+
+impl AlignedBuffer {
+    fn zeroed(len: usize, align: usize) -> AlignedBuffer {
+        let storage = vec![0u8; len + align];
+        let offset = storage.as_ptr().align_offset(align);
+        AlignedBuffer {
+            storage,
+            offset,
+            len,
+        }
+    }
+}
+
+impl Deref for AlignedBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.storage[self.offset..self.offset + self.len]
+    }
+}
+
+impl DerefMut for AlignedBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.storage[self.offset..self.offset + self.len]
     }
+}
 
-    if path.is_dir() {
+///Adds up the size of every file under the given paths, descending into directories if recurse is set.
+/// Anything which can't be read is counted as empty, since this is only meant for progress reporting
+pub fn total_file_size(paths: &[&Path], options: &WalkOptions) -> u64 {
+    paths
+        .iter()
+        .map(|p| path_size(p, options, true, &mut WalkState::new(p, options)))
+        .sum()
+}
+
+fn path_size(path: &Path, options: &WalkOptions, top_level: bool, state: &mut WalkState) -> u64 {
+    if options.filter.prunes(path, state.depth) {
+        return 0;
+    }
+    let metadata = match options.symlinks.follows(top_level) && !state.is_ancestor(path) {
+        true => fs::metadata(path),
+        false => fs::symlink_metadata(path),
+    };
+    match metadata {
+        Ok(m) if m.is_dir() && options.recurse => match fs::read_dir(path) {
+            Ok(_) if !top_level && state.crosses_device(path, Some(m)) => 0,
+            Ok(entries) => {
+                state.enter(path, options);
+                let size = entries
+                    .filter_map(Result::ok)
+                    .map(|e| path_size(&e.path(), options, false, state))
+                    .sum();
+                state.leave();
+                size
+            }
+            Err(_) => 0,
+        },
+        Ok(m)
+            if m.is_file()
+                && !options
+                    .filter
+                    .selects(path, state.depth, EntryType::File, || file_times(&m)) =>
+        {
+            0
+        }
+        #[cfg(unix)]
+        Ok(m) if m.is_file() => {
+            //Holes in sparse files don't get overwritten
+            use std::os::unix::fs::MetadataExt;
+            m.len().min(m.blocks() * 512)
+        }
+        #[cfg(not(unix))]
+        Ok(m) if m.is_file() => m.len(),
+        _ => 0,
+    }
+}
+
+///Lists a path and everything under it along with the size of each file, in the order a walk reaches them, so a
+/// directory comes after everything inside it. Directories and anything else without contents count as empty, and
+/// links are listed themselves rather than followed. Anything which can't be read is left out
+pub fn tree_entries(path: &Path) -> Vec<(PathBuf, u64)> {
+    let mut entries = Vec::new();
+    add_tree_entries(path, &mut entries);
+    entries
+}
+
+fn add_tree_entries(path: &Path, entries: &mut Vec<(PathBuf, u64)>) {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return;
+    };
+    if metadata.is_dir() {
+        if let Ok(dir) = fs::read_dir(path) {
+            for entry in dir.filter_map(Result::ok) {
+                add_tree_entries(&entry.path(), entries);
+            }
+        }
+    }
+    let size = match metadata.is_file() {
+        true => metadata.len(),
+        false => 0,
+    };
+    entries.push((path.to_path_buf(), size));
+}
+
+///Removes a file or an empty directory. Symlinks are removed themselves, never their targets
+pub fn remove_file_or_empty_dir(path: &Path) -> std::io::Result<()> {
+    if fs::symlink_metadata(path)?.is_dir() {
         fs::remove_dir(path)?;
     } else {
         fs::remove_file(path)?;
@@ -144,50 +1251,86 @@ where
     input_paths
         .into_iter()
         .filter_map(|p| {
-            if p.as_ref().exists() {
+            //Broken symlinks still exist as far as we're concerned, they can be removed like anything else
+            if fs::symlink_metadata(p.as_ref()).is_ok() {
                 Some(p)
             } else {
                 d_cb(p);
-                return None;
+                None
             }
         })
         .collect()
 }
 
 //Unfortunately I'm yet to find a more functional way to do this
-pub fn path_vec_from_string_vec<'a>(strings: Vec<&'a String>) -> Vec<&'a Path> {
+pub fn path_vec_from_string_vec(strings: Vec<&String>) -> Vec<&Path> {
     let mut ret_vec = Vec::<&Path>::new();
     for s in strings {
         ret_vec.push(Path::new(s));
     }
-    return ret_vec;
+    ret_vec
 }
 
-pub fn trash_items_to_names(items: &Vec<TrashItem>) -> Vec<String> {
+///Returns the path of the file backing an item in the trash. Only the freedesktop trash exposes this
+#[cfg(target_os = "linux")]
+pub fn trash_item_backing_path(item: &TrashItem) -> Option<PathBuf> {
+    //On freedesktop systems the id of an item is the path to its .trashinfo file, which lives next to
+    //the files directory and shares its name with the backing file
+    let info_file = Path::new(&item.id);
+    let trash_dir = info_file.parent()?.parent()?;
+    Some(trash_dir.join("files").join(info_file.file_stem()?))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn trash_item_backing_path(_item: &TrashItem) -> Option<PathBuf> {
+    None
+}
+
+pub fn trash_items_to_names(items: &[TrashItem]) -> Vec<String> {
     items.iter().map(|i| i.name.clone()).collect()
 }
 
-pub fn trash_items_from_names(names: &Vec<String>, items: &Vec<TrashItem>) -> Vec<TrashItem> {
-    let mut filtered_names = names.clone();
-    filtered_names.dedup();
+pub fn trash_items_from_names(names: &[String], items: &[TrashItem]) -> Vec<TrashItem> {
     items
         .iter()
-        .filter_map(|i| {
-            if names.contains(&i.name) {
-                return Some(i);
-            }
-            None
-        })
+        .filter(|i| names.contains(&i.name))
         .cloned()
         .collect()
 }
 
+///Fixtures shared by the tests of every module
 #[cfg(test)]
-mod tests {
+pub(crate) mod test_util {
     use rand::distributions::{Alphanumeric, DistString};
-    use std::{fs::OpenOptions, io::Read};
+
+    ///Random name for a file or directory in the working directory
+    pub(crate) fn generate_random_filename() -> String {
+        Alphanumeric.sample_string(&mut rand::thread_rng(), 8)
+            + "."
+            + &Alphanumeric.sample_string(&mut rand::thread_rng(), 3)
+    }
+
+    ///Creates a file with a random name holding the given contents, returning its name
+    pub(crate) fn temp_file(contents: impl AsRef<[u8]>) -> String {
+        let filename = generate_random_filename();
+        std::fs::write(&filename, contents).unwrap();
+        filename
+    }
+
+    ///Creates an empty directory with a random name, returning its name
+    pub(crate) fn temp_dir() -> String {
+        let dirname = generate_random_filename();
+        std::fs::create_dir(&dirname).unwrap();
+        dirname
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
 
     use super::*;
+    use test_util::{generate_random_filename, temp_file};
     #[test]
     fn test_select_from_trash_exists_single() {
         let filename = generate_random_filename();
@@ -250,6 +1393,7 @@ mod tests {
         let mut file = OpenOptions::new()
             .write(true)
             .create(true)
+            .truncate(true)
             .read(true)
             .open(&filename)
             .unwrap();
@@ -275,6 +1419,7 @@ mod tests {
         let mut file = OpenOptions::new()
             .write(true)
             .create(true)
+            .truncate(true)
             .read(true)
             .open(&filename)
             .unwrap();
@@ -284,7 +1429,7 @@ mod tests {
         file.write_all(&ones).unwrap();
         file.flush().unwrap();
 
-        overwrite_file(&file, 1).unwrap();
+        overwrite_file(&file, &OverwriteOptions::default()).unwrap();
 
         if !is_file_of_single_byte(&file, 0u8) {
             fs::remove_file(&filename).unwrap();
@@ -294,9 +1439,427 @@ mod tests {
         }
     }
 
-    fn generate_random_filename() -> String {
-        Alphanumeric.sample_string(&mut rand::thread_rng(), 8)
-            + "."
-            + &Alphanumeric.sample_string(&mut rand::thread_rng(), 3)
+    #[test]
+    fn test_overwrite_file_patterns() {
+        let filename = temp_file(vec![1u8; 100_000]);
+        let file = OpenOptions::new()
+            .write(true)
+            .read(true)
+            .open(&filename)
+            .unwrap();
+
+        let ones = OverwriteOptions {
+            pattern: OverwritePattern::Ones,
+            ..Default::default()
+        };
+        overwrite_file(&file, &ones).unwrap();
+        let overwritten_ones = is_file_of_single_byte(&file, 0xFF);
+
+        //An odd buffer size makes the random pattern repeat part way through the file, which verify has to follow
+        let random = OverwriteOptions {
+            runs: 2,
+            pattern: OverwritePattern::Random,
+            buffer_size: 5000,
+            verify: true,
+            ..Default::default()
+        };
+        let verified = overwrite_file(&file, &random);
+        let still_ones = is_file_of_single_byte(&file, 0xFF);
+        fs::remove_file(&filename).unwrap();
+
+        assert!(overwritten_ones);
+        assert!(verified.is_ok());
+        assert!(!still_ones);
+    }
+
+    //Cancels the overwrite as soon as the given pass starts, and keeps track of which passes were run
+    struct CancelAtPass {
+        cancel: Arc<AtomicBool>,
+        at: usize,
+        passes: Vec<usize>,
+    }
+
+    impl OverwriteObserver for CancelAtPass {
+        fn pass_started(&mut self, pass: usize, _runs: usize) {
+            self.passes.push(pass);
+            if pass == self.at {
+                self.cancel.store(true, Ordering::Relaxed);
+            }
+        }
+
+        fn bytes_written(&mut self, _bytes: u64) {}
+    }
+
+    #[test]
+    fn test_overwrite_file_cancelled() {
+        let filename = temp_file(vec![1u8; 100_000]);
+        let file = OpenOptions::new()
+            .write(true)
+            .read(true)
+            .open(&filename)
+            .unwrap();
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let mut opts = OverwriteOptions {
+            runs: 3,
+            pattern: OverwritePattern::Ones,
+            cancel: Some(cancel.clone()),
+            ..Default::default()
+        };
+        let mut observer = CancelAtPass {
+            cancel: cancel.clone(),
+            at: 2,
+            passes: Vec::new(),
+        };
+        let interrupted = overwrite_file_observed(&file, &opts, &mut observer);
+        let after_first_pass = is_file_of_single_byte(&file, 0xFF);
+
+        //Picking the overwrite back up only runs the pass that was interrupted and the ones after it
+        cancel.store(false, Ordering::Relaxed);
+        opts.first_pass = 2;
+        observer.at = 0;
+        observer.passes.clear();
+        let resumed = overwrite_file_observed(&file, &opts, &mut observer);
+        fs::remove_file(&filename).unwrap();
+
+        let err = interrupted.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+        assert_eq!(interrupted_pass(&err), Some(2));
+        assert!(after_first_pass);
+        assert!(resumed.is_ok());
+        assert_eq!(observer.passes, vec![2, 3]);
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    fn test_overwrite_file_io_uring() {
+        let filename = temp_file(vec![1u8; 10usize.pow(6) * 3 + 7]);
+        let file = OpenOptions::new()
+            .write(true)
+            .read(true)
+            .open(&filename)
+            .unwrap();
+
+        let opts = OverwriteOptions {
+            backend: WriteBackend::IoUring,
+            buffer_size: 64 * 1024,
+            ..Default::default()
+        };
+        let res = overwrite_file(&file, &opts);
+        let overwritten = is_file_of_single_byte(&file, 0u8);
+        fs::remove_file(&filename).unwrap();
+
+        //io_uring can be disabled by the kernel or a seccomp policy, in which case there's nothing to test
+        if let Err(e) = &res {
+            if matches!(e.raw_os_error(), Some(libc::ENOSYS) | Some(libc::EPERM)) {
+                return;
+            }
+        }
+        res.unwrap();
+        assert!(overwritten);
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    fn test_overwrite_file_io_uring_failure() {
+        let filename = temp_file(vec![1u8; 10usize.pow(6)]);
+        //Every write of the batch fails, which has to come back as an error rather than waiting on
+        //completions which were already taken
+        let file = File::open(&filename).unwrap();
+
+        let opts = OverwriteOptions {
+            backend: WriteBackend::IoUring,
+            buffer_size: 64 * 1024,
+            ..Default::default()
+        };
+        let res = overwrite_file(&file, &opts);
+        fs::remove_file(&filename).unwrap();
+
+        let err = res.unwrap_err();
+        if matches!(err.raw_os_error(), Some(libc::ENOSYS) | Some(libc::EPERM)) {
+            return;
+        }
+        assert_eq!(err.raw_os_error(), Some(libc::EBADF));
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    fn test_finish_short_write_direct() {
+        let filename = temp_file(vec![1u8; DIRECT_ALIGN * 3]);
+
+        let opts = OverwriteOptions {
+            direct_io: true,
+            buffer_size: DIRECT_ALIGN * 2,
+            ..Default::default()
+        };
+        let file = match open_for_overwrite(&filename, &opts) {
+            Ok(f) => f,
+            //Some filesystems (tmpfs for one) refuse O_DIRECT entirely
+            Err(e) if e.raw_os_error() == Some(libc::EINVAL) => {
+                fs::remove_file(&filename).unwrap();
+                return;
+            }
+            Err(e) => panic!("{e}"),
+        };
+        //Left off part way into the first block, which O_DIRECT can't start a write from
+        let buf = PatternBuffer::new(&opts);
+        let res = finish_short_write(&file, &buf, 0, 100, true);
+        let mut contents = Vec::new();
+        File::open(&filename)
+            .unwrap()
+            .read_to_end(&mut contents)
+            .unwrap();
+        fs::remove_file(&filename).unwrap();
+
+        res.unwrap();
+        assert!(contents[..DIRECT_ALIGN * 2].iter().all(|b| *b == 0));
+        assert!(contents[DIRECT_ALIGN * 2..].iter().all(|b| *b == 1));
+    }
+
+    #[test]
+    fn test_overwrite_file_observed() {
+        let filename = temp_file(vec![1u8; 10usize.pow(6) * 2 + 7]);
+
+        let file = OpenOptions::new().write(true).open(&filename).unwrap();
+        let mut counter = Counter {
+            passes: 0,
+            bytes: 0,
+        };
+        let opts = OverwriteOptions {
+            runs: 3,
+            ..Default::default()
+        };
+        overwrite_file_observed(&file, &opts, &mut counter).unwrap();
+        fs::remove_file(&filename).unwrap();
+
+        assert_eq!(counter.passes, 3);
+        assert_eq!(counter.bytes, (10u64.pow(6) * 2 + 7) * 3);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_data_extents_sparse() {
+        let filename = generate_random_filename();
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .read(true)
+            .open(&filename)
+            .unwrap();
+
+        //One block of data, a large hole, then another block of data at the end
+        file.write_all(&vec![1u8; 4096]).unwrap();
+        file.seek(io::SeekFrom::Start(16 * 1024 * 1024)).unwrap();
+        file.write_all(&vec![1u8; 4096]).unwrap();
+        file.sync_all().unwrap();
+
+        let len = file.metadata().unwrap().len();
+        let extents = data_extents(&file, len).unwrap();
+        overwrite_file(&file, &OverwriteOptions::default()).unwrap();
+        let overwritten = is_file_of_single_byte(&file, 0u8);
+        let blocks = {
+            use std::os::unix::fs::MetadataExt;
+            file.metadata().unwrap().blocks()
+        };
+        fs::remove_file(&filename).unwrap();
+
+        assert!(overwritten);
+        //Filesystems without hole support will report the whole file as one extent
+        if extents.len() > 1 {
+            assert_eq!(extents.first().unwrap().0, 0);
+            assert_eq!(extents.last().unwrap().1, len);
+            assert!(blocks * 512 < len);
+        }
+    }
+
+    struct Counter {
+        passes: usize,
+        bytes: u64,
+    }
+
+    impl OverwriteObserver for Counter {
+        fn pass_started(&mut self, pass: usize, _runs: usize) {
+            self.passes = pass;
+        }
+
+        fn bytes_written(&mut self, bytes: u64) {
+            self.bytes += bytes;
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_overwrite_file_slack() {
+        use std::os::unix::fs::MetadataExt;
+
+        let filename = temp_file(vec![1u8; 5000]);
+
+        let file = OpenOptions::new()
+            .write(true)
+            .read(true)
+            .open(&filename)
+            .unwrap();
+        let block_size = file.metadata().unwrap().blksize();
+        let mut counter = Counter {
+            passes: 0,
+            bytes: 0,
+        };
+        let opts = OverwriteOptions {
+            slack: true,
+            verify: true,
+            ..Default::default()
+        };
+        overwrite_file_observed(&file, &opts, &mut counter).unwrap();
+
+        let len = file.metadata().unwrap().len();
+        let overwritten = is_file_of_single_byte(&file, 0u8);
+        fs::remove_file(&filename).unwrap();
+
+        assert_eq!(len, 5000);
+        assert!(overwritten);
+        assert!(counter.bytes >= 5000);
+        assert_eq!(counter.bytes % block_size, 0);
+    }
+
+    #[test]
+    fn test_overwrite_file_size_change() {
+        //Appends to the file while it's being overwritten, as another process might
+        struct Appender {
+            path: String,
+            appends_left: usize,
+        }
+
+        impl OverwriteObserver for Appender {
+            fn bytes_written(&mut self, _bytes: u64) {
+                if self.appends_left > 0 {
+                    self.appends_left -= 1;
+                    let mut file = OpenOptions::new().append(true).open(&self.path).unwrap();
+                    file.write_all(&[1u8; 100]).unwrap();
+                }
+            }
+        }
+
+        let filename = temp_file(vec![1u8; 5000]);
+        let file = OpenOptions::new()
+            .write(true)
+            .read(true)
+            .open(&filename)
+            .unwrap();
+
+        //A single change gets picked up by overwriting the file again
+        let mut appender = Appender {
+            path: filename.clone(),
+            appends_left: 1,
+        };
+        let retried = overwrite_file_observed(&file, &OverwriteOptions::default(), &mut appender);
+        let overwritten = is_file_of_single_byte(&file, 0u8);
+
+        //A file that keeps changing gets reported
+        let mut appender = Appender {
+            path: filename.clone(),
+            appends_left: usize::MAX,
+        };
+        let failed = overwrite_file_observed(&file, &OverwriteOptions::default(), &mut appender);
+        fs::remove_file(&filename).unwrap();
+
+        assert!(retried.is_ok());
+        assert!(overwritten);
+        assert!(failed.is_err());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_punch_holes() {
+        let filename = temp_file(vec![1u8; 64 * 1024]);
+        let file = OpenOptions::new()
+            .write(true)
+            .read(true)
+            .open(&filename)
+            .unwrap();
+
+        let punched = punch_holes(&file).unwrap();
+        let len = file.metadata().unwrap().len();
+        let blocks = {
+            use std::os::unix::fs::MetadataExt;
+            file.metadata().unwrap().blocks()
+        };
+        fs::remove_file(&filename).unwrap();
+
+        assert_eq!(len, 64 * 1024);
+        if punched {
+            assert_eq!(blocks, 0);
+        }
+    }
+
+    #[test]
+    fn test_total_file_size() {
+        let dirname = generate_random_filename();
+        fs::create_dir_all(Path::new(&dirname).join("inner")).unwrap();
+        fs::write(Path::new(&dirname).join("a"), vec![0u8; 100]).unwrap();
+        fs::write(Path::new(&dirname).join("inner").join("b"), vec![0u8; 50]).unwrap();
+
+        let recurse = WalkOptions {
+            recurse: true,
+            ..Default::default()
+        };
+        let recursive = total_file_size(&[Path::new(&dirname)], &recurse);
+        let flat = total_file_size(&[Path::new(&dirname)], &WalkOptions::default());
+        fs::remove_dir_all(&dirname).unwrap();
+
+        assert_eq!(recursive, 150);
+        assert_eq!(flat, 0);
+    }
+
+    #[test]
+    fn test_tree_entries() {
+        let dirname = generate_random_filename();
+        fs::create_dir_all(Path::new(&dirname).join("inner")).unwrap();
+        fs::write(Path::new(&dirname).join("a"), vec![0u8; 100]).unwrap();
+        fs::write(Path::new(&dirname).join("inner").join("b"), vec![0u8; 50]).unwrap();
+
+        let entries = tree_entries(Path::new(&dirname));
+        let file = tree_entries(&Path::new(&dirname).join("a"));
+        fs::remove_dir_all(&dirname).unwrap();
+
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries.iter().map(|(_, size)| size).sum::<u64>(), 150);
+        assert_eq!(entries.last().unwrap(), &(PathBuf::from(&dirname), 0));
+        let inner = entries.iter().position(|(p, _)| p.ends_with("inner"));
+        let b = entries.iter().position(|(p, _)| p.ends_with("b"));
+        assert!(b < inner);
+        assert_eq!(file, vec![(Path::new(&dirname).join("a"), 100)]);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_overwrite_file_direct() {
+        let filename = generate_random_filename();
+
+        //Deliberately not a multiple of the block size so the unaligned tail gets exercised
+        let ones = vec![1u8; 10usize.pow(6) + 123];
+        fs::write(&filename, &ones).unwrap();
+
+        let opts = OverwriteOptions {
+            runs: 2,
+            direct_io: true,
+            verify: true,
+            ..Default::default()
+        };
+        let file = match open_for_overwrite(&filename, &opts) {
+            Ok(f) => f,
+            //Some filesystems (tmpfs for one) refuse O_DIRECT entirely
+            Err(e) if e.raw_os_error() == Some(libc::EINVAL) => {
+                fs::remove_file(&filename).unwrap();
+                return;
+            }
+            Err(e) => panic!("{e}"),
+        };
+        overwrite_file(&file, &opts).unwrap();
+
+        let check = File::open(&filename).unwrap();
+        let result = is_file_of_single_byte(&check, 0u8);
+        fs::remove_file(&filename).unwrap();
+        assert!(result);
     }
 }
@@ -0,0 +1,282 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+///What we know about the filesystem a path lives on
+#[derive(Clone, Debug, PartialEq)]
+pub struct FilesystemInfo {
+    pub fs_type: String,
+    pub mount_point: PathBuf,
+    ///Both the per mount and the superblock options
+    pub mount_options: Vec<String>,
+}
+
+impl FilesystemInfo {
+    ///Explains why overwriting a file in place on this filesystem might not destroy the original data,
+    /// or returns None if there's no known reason it wouldn't
+    pub fn overwrite_concern(&self) -> Option<&'static str> {
+        let concern = match self.fs_type.as_str() {
+            "btrfs" | "zfs" | "bcachefs" => Some(
+                "copy-on-write filesystem, overwrites are written to new blocks and the original data stays on disk until it is reused",
+            ),
+            "overlay" => Some(
+                "overlay filesystem, overwriting a file from a lower layer copies it up and leaves the original untouched",
+            ),
+            "f2fs" | "nilfs2" => Some(
+                "log-structured filesystem, overwrites are appended to the log instead of replacing the original blocks",
+            ),
+            "tmpfs" | "ramfs" => Some(
+                "memory backed filesystem, pages of the file may already have been written out to swap",
+            ),
+            "nfs" | "nfs4" | "cifs" | "smb3" | "fuse" => Some(
+                "network or userspace filesystem, there is no way of knowing how the data is actually stored",
+            ),
+            _ => None,
+        };
+        if concern.is_some() {
+            return concern;
+        }
+
+        if self.has_option("data=journal") {
+            return Some(
+                "data journaling is enabled, copies of the original data may remain in the journal",
+            );
+        }
+        if self.mount_options.iter().any(|o| o.starts_with("compress")) {
+            return Some(
+                "compression is enabled, compressed overwrites are stored in new extents instead of replacing the original ones",
+            );
+        }
+
+        None
+    }
+
+    fn has_option(&self, option: &str) -> bool {
+        self.mount_options.iter().any(|o| o == option)
+    }
+}
+
+//Magic numbers from statfs(2) for the filesystems we care about, plus the common ones which are fine
+#[cfg(target_os = "linux")]
+const FS_MAGICS: &[(u32, &str)] = &[
+    (0xEF53, "ext4"),
+    (0x58465342, "xfs"),
+    (0x9123683E, "btrfs"),
+    (0x2FC12FC1, "zfs"),
+    (0xCA451A4E, "bcachefs"),
+    (0x794C7630, "overlay"),
+    (0x01021994, "tmpfs"),
+    (0x858458F6, "ramfs"),
+    (0xF2F52010, "f2fs"),
+    (0x3434, "nilfs2"),
+    (0x6969, "nfs"),
+    (0xFF534D42, "cifs"),
+    (0xFE534D42, "smb3"),
+    (0x65735546, "fuse"),
+    (0x4D44, "vfat"),
+    (0x5346544E, "ntfs"),
+];
+
+///Finds out which filesystem a path is on, along with the options it was mounted with
+#[cfg(target_os = "linux")]
+pub fn filesystem_info(path: &Path) -> io::Result<FilesystemInfo> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let path = path.canonicalize()?;
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut stat) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mountinfo = std::fs::read_to_string("/proc/self/mountinfo")?;
+    let mount = parse_mountinfo(&mountinfo)
+        .into_iter()
+        .filter(|m| path.starts_with(&m.mount_point))
+        .max_by_key(|m| m.mount_point.as_os_str().len());
+
+    //The magic number is what the kernel is actually using, the name in mountinfo is just a fallback for
+    //filesystems we don't know the magic of
+    let fs_type = FS_MAGICS
+        .iter()
+        .find(|(magic, _)| *magic == stat.f_type as u32)
+        .map(|(_, name)| name.to_string());
+
+    match mount {
+        Some(m) => Ok(FilesystemInfo {
+            fs_type: fs_type.unwrap_or(m.fs_type),
+            mount_point: m.mount_point,
+            mount_options: m.mount_options,
+        }),
+        None => Ok(FilesystemInfo {
+            fs_type: fs_type.unwrap_or_else(|| format!("unknown ({:#x})", stat.f_type)),
+            mount_point: PathBuf::from("/"),
+            mount_options: vec![],
+        }),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn filesystem_info(_path: &Path) -> io::Result<FilesystemInfo> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "filesystem detection is only supported on Linux",
+    ))
+}
+
+///Returns the number of bytes available to unprivileged users on the filesystem a path is on
+#[cfg(target_os = "linux")]
+pub fn free_space(path: &Path) -> io::Result<u64> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn free_space(_path: &Path) -> io::Result<u64> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "checking free space is only supported on Linux",
+    ))
+}
+
+///A filesystem which overwriting files on can't be relied on to destroy their contents
+#[derive(Clone, Debug, PartialEq)]
+pub struct OverwriteConcern {
+    pub mount_point: PathBuf,
+    pub fs_type: String,
+    pub reason: &'static str,
+}
+
+///Checks the filesystems of the given paths, returning one concern for each filesystem which overwrites can't be
+/// relied on for. Filesystems which can't be identified are treated as a concern too, but not on platforms where
+/// they can't be identified at all, since there's nothing to tell them apart by
+pub fn overwrite_concerns(paths: &[&Path]) -> Vec<OverwriteConcern> {
+    let mut concerns: Vec<OverwriteConcern> = Vec::new();
+
+    for path in paths {
+        let concern = match filesystem_info(path) {
+            Ok(info) => info.overwrite_concern().map(|reason| OverwriteConcern {
+                mount_point: info.mount_point,
+                fs_type: info.fs_type,
+                reason,
+            }),
+            Err(e) if e.kind() == io::ErrorKind::Unsupported => None,
+            Err(_) => Some(OverwriteConcern {
+                mount_point: path.to_path_buf(),
+                fs_type: "unknown".to_string(),
+                reason: "the filesystem could not be identified",
+            }),
+        };
+
+        if let Some(c) = concern {
+            if !concerns.iter().any(|e| e.mount_point == c.mount_point) {
+                concerns.push(c);
+            }
+        }
+    }
+
+    concerns
+}
+
+///Parses the contents of /proc/self/mountinfo. Lines which don't match the format are skipped
+pub fn parse_mountinfo(contents: &str) -> Vec<FilesystemInfo> {
+    contents.lines().filter_map(parse_mountinfo_line).collect()
+}
+
+//The format is described in proc(5). The fields after the optional ones are separated from them by a lone "-"
+fn parse_mountinfo_line(line: &str) -> Option<FilesystemInfo> {
+    let (mount_fields, super_fields) = line.split_once(" - ")?;
+    let mount_fields: Vec<&str> = mount_fields.split(' ').collect();
+    let super_fields: Vec<&str> = super_fields.split(' ').collect();
+
+    let mount_point = unescape_mountinfo(mount_fields.get(4)?);
+    let mut mount_options: Vec<String> =
+        mount_fields.get(5)?.split(',').map(String::from).collect();
+    if let Some(options) = super_fields.get(2) {
+        mount_options.extend(options.split(',').map(String::from));
+    }
+
+    Some(FilesystemInfo {
+        fs_type: super_fields.first()?.to_string(),
+        mount_point: PathBuf::from(mount_point),
+        mount_options,
+    })
+}
+
+///Spaces, tabs, newlines and backslashes in mountinfo paths are escaped as octal
+fn unescape_mountinfo(field: &str) -> String {
+    let mut out = Vec::with_capacity(field.len());
+    let bytes = field.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' && i + 4 <= bytes.len() {
+            let code = std::str::from_utf8(&bytes[i + 1..i + 4])
+                .ok()
+                .and_then(|o| u8::from_str_radix(o, 8).ok());
+            if let Some(c) = code {
+                out.push(c);
+                i += 4;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MOUNTINFO: &str = "\
+22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw,errors=remount-ro
+23 22 8:2 / /home rw,relatime shared:2 - btrfs /dev/sda2 rw,compress=zstd:3,space_cache=v2
+24 22 0:21 / /mnt/my\\040disk rw,nosuid - ext4 /dev/sdb1 rw,data=journal
+25 22 0:22 / /tmp rw,nosuid,nodev shared:3 - tmpfs tmpfs rw,size=1024k
+not a mountinfo line";
+
+    #[test]
+    fn test_parse_mountinfo() {
+        let mounts = parse_mountinfo(MOUNTINFO);
+
+        assert_eq!(mounts.len(), 4);
+        assert_eq!(mounts[0].fs_type, "ext4");
+        assert_eq!(mounts[0].mount_point, PathBuf::from("/"));
+        assert_eq!(mounts[2].mount_point, PathBuf::from("/mnt/my disk"));
+        assert!(mounts[1].has_option("compress=zstd:3"));
+        assert!(mounts[2].has_option("nosuid"));
+        assert!(mounts[2].has_option("data=journal"));
+    }
+
+    #[test]
+    fn test_overwrite_concern() {
+        let mounts = parse_mountinfo(MOUNTINFO);
+
+        assert!(mounts[0].overwrite_concern().is_none());
+        assert!(mounts[1].overwrite_concern().is_some());
+        assert!(mounts[2]
+            .overwrite_concern()
+            .is_some_and(|c| c.contains("journal")));
+        assert!(mounts[3].overwrite_concern().is_some());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_filesystem_info() {
+        let info = filesystem_info(Path::new(".")).unwrap();
+
+        assert!(!info.fs_type.is_empty());
+        assert!(Path::new(".")
+            .canonicalize()
+            .unwrap()
+            .starts_with(&info.mount_point));
+    }
+}
@@ -0,0 +1,169 @@
+use std::{path::Path, str::FromStr, time::SystemTime};
+
+use glob::{MatchOptions, Pattern};
+
+///The kinds of entry a filter can pick out
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EntryType {
+    File,
+    Dir,
+    Symlink,
+    ///Devices, sockets and pipes, which are never picked out by type
+    Other,
+}
+
+impl FromStr for EntryType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "f" => Ok(EntryType::File),
+            "d" => Ok(EntryType::Dir),
+            "l" => Ok(EntryType::Symlink),
+            other => Err(format!("'{other}' is not a type, expected f, d or l")),
+        }
+    }
+}
+
+///Narrows down what a walk runs the callbacks on. Everything is checked in the walkers, so that excluded
+/// directories are never descended into. Anything left out counts as skipped, so the directories containing it
+/// are left in place, and a directory is only ever passed to the callbacks once everything inside it has been
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Filter {
+    ///Only files matching one of these are selected. Directories are always walked, since what's inside them
+    /// might match
+    pub include: Vec<Pattern>,
+    ///Anything matching one of these is left out, and isn't descended into if it's a directory
+    pub exclude: Vec<Pattern>,
+    ///The types of entry which are selected, or every type if this is empty
+    pub types: Vec<EntryType>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    ///Only files last modified before this are selected
+    pub older_than: Option<SystemTime>,
+    ///Only files last modified after this are selected
+    pub newer_than: Option<SystemTime>,
+    ///How deep into the tree the walk has to be for anything to be selected. The paths given to the walk are at
+    /// depth 0, so 1 leaves them alone and only acts on what's inside them
+    pub min_depth: Option<usize>,
+    ///How deep into the tree the walk goes. Directories at this depth are only read to see whether they're empty
+    pub max_depth: Option<usize>,
+}
+
+impl Filter {
+    ///Returns whether an entry is left out of the walk without looking at it any further. Directories left out
+    /// like this aren't descended into
+    pub fn prunes(&self, path: &Path, depth: usize) -> bool {
+        self.max_depth.is_some_and(|m| depth > m) || matching_pattern(&self.exclude, path).is_some()
+    }
+
+    ///Whether the size or modification time of files are needed to select them
+    pub fn needs_metadata(&self) -> bool {
+        self.min_size.is_some()
+            || self.max_size.is_some()
+            || self.older_than.is_some()
+            || self.newer_than.is_some()
+    }
+
+    ///Returns whether the callbacks are run on an entry which wasn't pruned. Only the type and depth of
+    /// directories are looked at, while the patterns, sizes and times apply to everything else. The size and
+    /// modification time are only asked for when they're needed, and files they can't be found for aren't
+    /// selected
+    pub fn selects(
+        &self,
+        path: &Path,
+        depth: usize,
+        entry_type: EntryType,
+        metadata: impl FnOnce() -> Option<(u64, SystemTime)>,
+    ) -> bool {
+        if self.min_depth.is_some_and(|m| depth < m)
+            || (!self.types.is_empty() && !self.types.contains(&entry_type))
+        {
+            return false;
+        }
+        if entry_type == EntryType::Dir {
+            return true;
+        }
+        if !self.include.is_empty() && matching_pattern(&self.include, path).is_none() {
+            return false;
+        }
+        if !self.needs_metadata() {
+            return true;
+        }
+
+        let Some((size, modified)) = metadata() else {
+            return false;
+        };
+        self.min_size.is_none_or(|m| size >= m)
+            && self.max_size.is_none_or(|m| size <= m)
+            && self.older_than.is_none_or(|t| modified < t)
+            && self.newer_than.is_none_or(|t| modified > t)
+    }
+}
+
+///Returns the first of the glob patterns a path matches. Patterns without a separator are matched against the
+/// file name, and the rest against the whole path
+pub fn matching_pattern<'a>(patterns: &'a [Pattern], path: &Path) -> Option<&'a Pattern> {
+    let options = MatchOptions {
+        require_literal_separator: true,
+        ..Default::default()
+    };
+    let name = path.file_name().map(Path::new);
+    patterns.iter().find(|p| match p.as_str().contains('/') {
+        true => p.matches_path_with(path, options),
+        false => name.is_some_and(|n| p.matches_path_with(n, options)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_filter_selects() {
+        let filter = Filter {
+            include: vec![Pattern::new("*.log").unwrap()],
+            exclude: vec![
+                Pattern::new("keep").unwrap(),
+                Pattern::new("a/b/*").unwrap(),
+            ],
+            min_size: Some(10),
+            older_than: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(100)),
+            min_depth: Some(1),
+            max_depth: Some(3),
+            ..Default::default()
+        };
+        let old = || Some((20, SystemTime::UNIX_EPOCH));
+
+        assert!(filter.prunes(Path::new("a/keep"), 1));
+        assert!(filter.prunes(Path::new("a/b/c"), 2));
+        assert!(filter.prunes(Path::new("a/c/d/e"), 4));
+        assert!(!filter.prunes(Path::new("a/c/d"), 3));
+
+        assert!(filter.selects(Path::new("a/x.log"), 1, EntryType::File, old));
+        assert!(filter.selects(Path::new("a/dir"), 1, EntryType::Dir, || None));
+        assert!(!filter.selects(Path::new("a"), 0, EntryType::Dir, || None));
+        assert!(!filter.selects(Path::new("a/x.txt"), 1, EntryType::File, old));
+        assert!(
+            !filter.selects(Path::new("a/x.log"), 1, EntryType::File, || {
+                Some((5, SystemTime::UNIX_EPOCH))
+            })
+        );
+        assert!(
+            !filter.selects(Path::new("a/x.log"), 1, EntryType::File, || {
+                Some((20, SystemTime::now()))
+            })
+        );
+        assert!(!filter.selects(Path::new("a/x.log"), 1, EntryType::File, || None));
+
+        let files_only = Filter {
+            types: vec!["f".parse().unwrap()],
+            ..Default::default()
+        };
+        assert!(!files_only.selects(Path::new("dir"), 0, EntryType::Dir, || None));
+        assert!(!files_only.selects(Path::new("link"), 0, EntryType::Symlink, || None));
+        assert!("x".parse::<EntryType>().is_err());
+    }
+}
@@ -0,0 +1,197 @@
+use std::{
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use rand::distributions::{Alphanumeric, DistString};
+
+use crate::{
+    files::{self, OverwriteObserver, OverwriteOptions, PatternBuffer},
+    filesystem,
+};
+
+///Everything controlling how free space gets wiped
+#[derive(Clone, Debug, Default)]
+pub struct WipeFreeOptions {
+    ///The free space is always written once, so runs, verify and slack are ignored
+    pub ow_options: OverwriteOptions,
+    ///Number of bytes of free space to leave unwritten
+    pub reserve: u64,
+}
+
+///How much free space was wiped, and whether the wipe was cut short
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct WipedSpace {
+    pub bytes: u64,
+    pub fill_files: usize,
+    pub interrupted: bool,
+}
+
+//Fill files are capped so that filesystems with a small maximum file size (FAT32's is 4GiB) can still be filled
+const FILL_FILE_SIZE: u64 = 1 << 30;
+//How much is written between checks of the cancel flag and the reserve
+const FILL_CHUNK_SIZE: u64 = 16 << 20;
+
+///Overwrites the free space of the filesystem a directory is on by filling it with files of the overwrite pattern
+/// until the filesystem runs out of space, or only the reserve is left. The fill files are always removed
+/// before returning, including when the wipe is cancelled by setting the cancel flag or fails
+pub fn wipe_free_space(
+    dir: &Path,
+    options: &WipeFreeOptions,
+    observer: &mut dyn OverwriteObserver,
+    cancel: &AtomicBool,
+) -> io::Result<WipedSpace> {
+    let fill_dir = FillDir::create(dir)?;
+    let buf = PatternBuffer::new(&options.ow_options);
+    let mut wiped = WipedSpace::default();
+
+    let available = filesystem::free_space(dir)?;
+    observer.overwrite_started(available.saturating_sub(options.reserve), 1);
+    observer.pass_started(1, 1);
+
+    'fill: loop {
+        let path = fill_dir.path.join(format!("fill{}", wiped.fill_files));
+        //When the last fill file ends up filling the filesystem exactly, it's only the next one which finds out
+        let file = match File::create(&path)
+            .and_then(|_| files::open_for_overwrite(&path, &options.ow_options))
+        {
+            Ok(file) => file,
+            Err(e) if is_out_of_space(&e) => break,
+            Err(e) => return Err(e),
+        };
+        wiped.fill_files += 1;
+
+        let mut len = 0;
+        let full = loop {
+            if cancel.load(Ordering::Relaxed) {
+                wiped.interrupted = true;
+                break true;
+            }
+            if len == FILL_FILE_SIZE {
+                break false;
+            }
+
+            let mut chunk = FILL_CHUNK_SIZE.min(FILL_FILE_SIZE - len);
+            if options.reserve > 0 {
+                let available = filesystem::free_space(dir)?;
+                if available <= options.reserve {
+                    break true;
+                }
+                chunk = chunk.min(available - options.reserve);
+            }
+
+            match files::write_pattern(&file, len, len + chunk, &buf, &options.ow_options, observer)
+            {
+                Ok(()) => len += chunk,
+                Err(e) if is_out_of_space(&e) => break true,
+                Err(e) => return Err(e),
+            }
+        };
+
+        //With delayed allocation running out of space may only show up once the data is synced
+        match file.sync_data() {
+            Ok(()) => {}
+            Err(e) if is_out_of_space(&e) => break 'fill,
+            Err(e) => return Err(e),
+        }
+        if full {
+            break;
+        }
+    }
+
+    for entry in fs::read_dir(&fill_dir.path)? {
+        wiped.bytes += entry?.metadata()?.len();
+    }
+    fill_dir.remove()?;
+
+    Ok(wiped)
+}
+
+fn is_out_of_space(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::StorageFull | io::ErrorKind::QuotaExceeded | io::ErrorKind::WriteZero
+    )
+}
+
+///Hidden directory holding the fill files, which removes itself along with them when dropped so that nothing
+/// is left behind by an error or a panic
+struct FillDir {
+    path: PathBuf,
+}
+
+impl FillDir {
+    fn create(dir: &Path) -> io::Result<FillDir> {
+        let name = format!(
+            ".rrc-wipe-{}",
+            Alphanumeric.sample_string(&mut rand::thread_rng(), 8)
+        );
+        let path = dir.join(name);
+        fs::create_dir(&path)?;
+        Ok(FillDir { path })
+    }
+
+    fn remove(self) -> io::Result<()> {
+        fs::remove_dir_all(&self.path)
+    }
+}
+
+impl Drop for FillDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::files::test_util::temp_dir;
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_wipe_free_space_reserve() {
+        let dirname = temp_dir();
+
+        //Only wipe a few MiB, leaving everything else as the reserve
+        let available = filesystem::free_space(Path::new(&dirname)).unwrap();
+        let options = WipeFreeOptions {
+            reserve: available.saturating_sub(8 << 20),
+            ..Default::default()
+        };
+        let wiped = wipe_free_space(
+            Path::new(&dirname),
+            &options,
+            &mut (),
+            &AtomicBool::new(false),
+        );
+        let leftovers = fs::read_dir(&dirname).unwrap().count();
+        fs::remove_dir(&dirname).unwrap();
+
+        let wiped = wiped.unwrap();
+        assert!(!wiped.interrupted);
+        assert!(wiped.bytes > 0 && wiped.bytes <= 16 << 20);
+        assert_eq!(leftovers, 0);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_wipe_free_space_cancelled() {
+        let dirname = temp_dir();
+
+        let wiped = wipe_free_space(
+            Path::new(&dirname),
+            &WipeFreeOptions::default(),
+            &mut (),
+            &AtomicBool::new(true),
+        );
+        let leftovers = fs::read_dir(&dirname).unwrap().count();
+        fs::remove_dir(&dirname).unwrap();
+
+        let wiped = wiped.unwrap();
+        assert!(wiped.interrupted);
+        assert_eq!(wiped.bytes, 0);
+        assert_eq!(leftovers, 0);
+    }
+}
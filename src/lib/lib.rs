@@ -1,27 +1,98 @@
-use std::{
-    error::Error,
-    fmt::Display,
-    fs::File,
-    path::{Path, PathBuf},
-};
+use std::{error::Error, fmt::Display, path::Path};
+
+use protect::ProtectedPath;
 
 pub mod files;
+pub mod filesystem;
+pub mod filter;
+pub mod freespace;
+mod parallel;
+pub mod plan;
+pub mod protect;
+pub mod report;
+pub mod resume;
+pub mod shred;
+pub mod throttle;
 pub mod util;
+#[cfg(target_os = "linux")]
+pub mod walker;
 
 ///Trait to be used with the recurse_op_on_dir function.
 /// Guarantee to the implementor: display_cb MUST be called before cb to allow for prompting etc.
 pub trait RecursiveCallback {
-    fn execute_callbacks(&mut self, path: &PathBuf, is_dir: bool) -> Result<bool, FileErr> {
+    fn execute_callbacks(&mut self, path: &Path, is_dir: bool) -> Result<bool, FileErr> {
         let display_cb_result = self.display_cb(path, is_dir);
         let cb_result = self.cb(path)?;
         Ok(display_cb_result && cb_result)
     }
+    #[cfg(target_os = "linux")]
+    fn execute_callbacks_at(&mut self, entry: &walker::EntryAt) -> Result<bool, FileErr> {
+        let display_cb_result = self.display_cb(entry.path(), entry.is_dir());
+        let cb_result = self.cb_at(entry)?;
+        Ok(display_cb_result && cb_result)
+    }
     ///Processes a file that has been discovered while traversing the tree.
     /// Returns true if the traversal should continue
-    fn cb(&mut self, path: &PathBuf) -> Result<bool, FileErr>;
+    fn cb(&mut self, path: &Path) -> Result<bool, FileErr>;
+    ///Same as cb, but given the file relative to the directory it was found in, so that it can be acted on
+    /// without resolving its path again. Calls cb with the path unless it's implemented
+    #[cfg(target_os = "linux")]
+    fn cb_at(&mut self, entry: &walker::EntryAt) -> Result<bool, FileErr> {
+        self.cb(entry.path())
+    }
     ///Displays any relevant output to the user about the current file being parsed.
     /// Returns true if the traversal should continue
-    fn display_cb(&mut self, path: &PathBuf, is_dir: bool) -> bool;
+    fn display_cb(&mut self, path: &Path, is_dir: bool) -> bool;
+    ///Called instead of the other callbacks for a mount point which wasn't descended into, because the walk is
+    /// staying on one filesystem
+    fn mount_skipped(&mut self, _path: &Path) {}
+    ///Called instead of the other callbacks for something inside the tree which is protected. The directories
+    /// containing it are left in place
+    fn protected_skipped(&mut self, _protected: &ProtectedPath) {}
+    ///Called with any error hit while walking the tree. Returning the error stops the walk with it, which is what
+    /// happens unless this is implemented. Returning Ok carries on with the rest of the tree instead, and the
+    /// directories containing the failed file are skipped since they can't be processed either
+    fn failed(&mut self, err: FileErr) -> Result<(), FileErr> {
+        Err(err)
+    }
+}
+
+///Thread safe counterpart to RecursiveCallback for use with recurse_on_paths_parallel. The callbacks may be run
+/// from several threads at once, but a directory is only ever passed to them after everything inside it
+pub trait ParallelCallback: Sync {
+    fn execute_callbacks(&self, path: &Path, is_dir: bool) -> Result<bool, FileErr> {
+        let display_cb_result = self.display_cb(path, is_dir);
+        let cb_result = self.cb(path)?;
+        Ok(display_cb_result && cb_result)
+    }
+    #[cfg(target_os = "linux")]
+    fn execute_callbacks_at(&self, entry: &walker::EntryAt) -> Result<bool, FileErr> {
+        let display_cb_result = self.display_cb(entry.path(), entry.is_dir());
+        let cb_result = self.cb_at(entry)?;
+        Ok(display_cb_result && cb_result)
+    }
+    ///Processes a file that has been discovered while traversing the tree.
+    /// Returns true if the traversal should continue
+    fn cb(&self, path: &Path) -> Result<bool, FileErr>;
+    ///Same as cb, but given the file relative to the directory it was found in. Calls cb with the path unless
+    /// it's implemented
+    #[cfg(target_os = "linux")]
+    fn cb_at(&self, entry: &walker::EntryAt) -> Result<bool, FileErr> {
+        self.cb(entry.path())
+    }
+    ///Displays any relevant output to the user about the current file being parsed.
+    /// Returns true if the traversal should continue
+    fn display_cb(&self, path: &Path, is_dir: bool) -> bool;
+    ///Called instead of the other callbacks for a mount point which wasn't descended into, because the walk is
+    /// staying on one filesystem
+    fn mount_skipped(&self, _path: &Path) {}
+    ///Called instead of the other callbacks for something inside the tree which is protected
+    fn protected_skipped(&self, _protected: &ProtectedPath) {}
+    ///Called with any error hit while walking the tree. Returning the error stops the walk with it, and returning
+    /// Ok skips the failed file and the directories containing it
+    fn failed(&self, err: FileErr) -> Result<(), FileErr> {
+        Err(err)
+    }
 }
 
 #[derive(Debug)]
@@ -43,6 +114,17 @@ impl FileErr {
             file: files::path_to_string(path),
         }
     }
+
+    pub fn kind(&self) -> std::io::ErrorKind {
+        self.source.kind()
+    }
+
+    ///Returns what was protected if this is a walk being refused a protected path
+    pub fn protected(&self) -> Option<&ProtectedPath> {
+        self.source
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<ProtectedPath>())
+    }
 }
 
 impl Error for FileErr {
@@ -53,25 +135,649 @@ impl Error for FileErr {
 
 ///This function runs the callbacks of a RecursiveOperation on a given set of paths.
 /// Please note that it does not check if the path exists.
+/// On Linux the tree is walked relative to directory file descriptors, see walker::walk_paths.
+/// Returns the number of paths the callbacks succeeded on, so anything which failed isn't counted
+#[cfg(target_os = "linux")]
+pub fn recurse_on_paths<T: RecursiveCallback>(
+    op: &mut T,
+    paths: Vec<&Path>,
+    options: &files::WalkOptions,
+) -> Result<usize, FileErr> {
+    walker::walk_paths(op, paths, options)
+}
+
+///This function runs the callbacks of a RecursiveOperation on a given set of paths.
+/// Please note that it does not check if the path exists.
+/// Returns the number of paths the callbacks succeeded on, so anything which failed isn't counted
+#[cfg(not(target_os = "linux"))]
 pub fn recurse_on_paths<T: RecursiveCallback>(
     op: &mut T,
     paths: Vec<&Path>,
-    recurse: bool,
+    options: &files::WalkOptions,
 ) -> Result<usize, FileErr> {
     let mut counter: usize = 0;
 
-    for path in paths {
-        if path.is_dir() && recurse {
-            match files::run_op_on_dir_recursive::<T>(op, path, 0) {
-                Ok(c) => counter += c.0,
+    for path in files::unprotected_paths(paths, options, |e| op.failed(e))? {
+        if files::is_walkable_dir(path, options) {
+            match files::run_op_on_dir_recursive::<T>(op, path, 0, options) {
+                Ok(c) => {
+                    counter += c.0;
+                    if !c.1 {
+                        break;
+                    }
+                }
                 Err(e) => {
                     return Err(e);
                 }
             };
         } else {
-            op.execute_callbacks(&PathBuf::from(path), false)?;
+            match op.execute_callbacks(path, false) {
+                Ok(true) => counter += 1,
+                Ok(false) => {
+                    counter += 1;
+                    break;
+                }
+                Err(e) => op.failed(e)?,
+            }
         }
     }
 
     Ok(counter)
 }
+
+///Parallel version of recurse_on_paths. The tree is walked by a pool of worker threads, which read directories
+/// and run the callbacks on files at the same time. A directory is only passed to the callbacks once everything
+/// inside it has been, so they can still remove it. On Linux it's walked relative to directory file descriptors
+/// like in walker::walk_paths, with one held open for each directory being read or waiting on what's inside it. The first error the callbacks don't carry on from stops any
+/// further work from being started, and is returned once the workers have finished what they're doing
+pub fn recurse_on_paths_parallel<T: ParallelCallback>(
+    op: &T,
+    paths: Vec<&Path>,
+    options: &files::WalkOptions,
+    jobs: usize,
+) -> Result<usize, FileErr> {
+    parallel::walk_parallel(op, paths, options, jobs)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, sync::Mutex};
+
+    use super::*;
+    use crate::files::test_util::generate_random_filename;
+
+    struct Remover;
+
+    impl ParallelCallback for Remover {
+        fn cb(&self, path: &Path) -> Result<bool, FileErr> {
+            files::remove_file_or_empty_dir(path).map_err(|e| FileErr::map(e, path))?;
+            Ok(true)
+        }
+
+        fn display_cb(&self, _path: &Path, _is_dir: bool) -> bool {
+            true
+        }
+    }
+
+    impl RecursiveCallback for Remover {
+        fn cb(&mut self, path: &Path) -> Result<bool, FileErr> {
+            ParallelCallback::cb(self, path)
+        }
+
+        fn display_cb(&mut self, _path: &Path, _is_dir: bool) -> bool {
+            true
+        }
+    }
+
+    //Makes base/tree/file and base/outside/file, with base/tree/link pointing at outside and base/top pointing at tree
+    #[cfg(unix)]
+    fn symlink_fixture() -> String {
+        let base = generate_random_filename();
+        let base_path = Path::new(&base);
+        fs::create_dir_all(base_path.join("tree")).unwrap();
+        fs::create_dir_all(base_path.join("outside")).unwrap();
+        fs::write(base_path.join("tree").join("file"), b"data").unwrap();
+        fs::write(base_path.join("outside").join("file"), b"data").unwrap();
+        std::os::unix::fs::symlink("../outside", base_path.join("tree").join("link")).unwrap();
+        std::os::unix::fs::symlink("tree", base_path.join("top")).unwrap();
+        base
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_recurse_on_paths_never_follows_symlinks() {
+        let walk = files::WalkOptions {
+            recurse: true,
+            ..Default::default()
+        };
+
+        let base = symlink_fixture();
+        let tree = Path::new(&base).join("tree");
+        let count = recurse_on_paths(&mut Remover, vec![&tree], &walk);
+        let tree_exists = tree.exists();
+        let outside_kept = Path::new(&base).join("outside").join("file").exists();
+        //A link given on the command line is removed as a link too
+        let top = Path::new(&base).join("top");
+        fs::create_dir(&tree).unwrap();
+        recurse_on_paths(&mut Remover, vec![&top], &walk).unwrap();
+        let top_exists = fs::symlink_metadata(&top).is_ok();
+        let tree_kept = tree.exists();
+        fs::remove_dir_all(&base).unwrap();
+
+        let base = symlink_fixture();
+        let tree = Path::new(&base).join("tree");
+        let parallel = recurse_on_paths_parallel(&Remover, vec![&tree], &walk, 4);
+        let parallel_outside_kept = Path::new(&base).join("outside").join("file").exists();
+        fs::remove_dir_all(&base).unwrap();
+
+        assert_eq!(count.unwrap(), 3);
+        assert!(!tree_exists);
+        assert!(outside_kept);
+        assert!(!top_exists);
+        assert!(tree_kept);
+        assert_eq!(parallel.unwrap(), 3);
+        assert!(parallel_outside_kept);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_recurse_on_paths_follows_command_line_symlinks() {
+        let walk = files::WalkOptions {
+            recurse: true,
+            symlinks: files::SymlinkPolicy::CommandLine,
+            ..Default::default()
+        };
+
+        let base = symlink_fixture();
+        let top = Path::new(&base).join("top");
+        recurse_on_paths(&mut Remover, vec![&top], &walk).unwrap();
+        let top_exists = fs::symlink_metadata(&top).is_ok();
+        let tree_file_exists = Path::new(&base).join("tree").join("file").exists();
+        let outside_kept = Path::new(&base).join("outside").join("file").exists();
+        fs::remove_dir_all(&base).unwrap();
+
+        //The link itself is removed, but the directory it pointed at is only emptied
+        assert!(!top_exists);
+        assert!(!tree_file_exists);
+        assert!(outside_kept);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_recurse_on_paths_follows_symlinks() {
+        let walk = files::WalkOptions {
+            recurse: true,
+            symlinks: files::SymlinkPolicy::Always,
+            ..Default::default()
+        };
+
+        let base = symlink_fixture();
+        let tree = Path::new(&base).join("tree");
+        //A link back to the tree mustn't be walked forever
+        std::os::unix::fs::symlink(".", tree.join("loop")).unwrap();
+        let count = recurse_on_paths(&mut Remover, vec![&tree], &walk);
+        let tree_exists = tree.exists();
+        let outside = Path::new(&base).join("outside");
+        let outside_emptied = outside.exists() && fs::read_dir(&outside).unwrap().count() == 0;
+        fs::remove_dir_all(&base).unwrap();
+
+        assert_eq!(count.unwrap(), 5);
+        assert!(!tree_exists);
+        assert!(outside_emptied);
+    }
+
+    //Removes everything except files called bad
+    struct FailOnBad;
+
+    impl ParallelCallback for FailOnBad {
+        fn cb(&self, path: &Path) -> Result<bool, FileErr> {
+            if path.ends_with("bad") {
+                return Err(FileErr::map(
+                    std::io::Error::from(std::io::ErrorKind::PermissionDenied),
+                    path,
+                ));
+            }
+            Remover.cb(path)
+        }
+
+        fn display_cb(&self, _path: &Path, _is_dir: bool) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_recurse_on_paths_parallel_error() {
+        let dirname = generate_random_filename();
+        let bad_dir = Path::new(&dirname).join("a").join("b");
+        fs::create_dir_all(&bad_dir).unwrap();
+        fs::write(bad_dir.join("bad"), b"data").unwrap();
+
+        let walk = files::WalkOptions {
+            recurse: true,
+            ..Default::default()
+        };
+        let res = recurse_on_paths_parallel(&FailOnBad, vec![Path::new(&dirname)], &walk, 4);
+        let bad_dir_exists = bad_dir.exists();
+        fs::remove_dir_all(&dirname).unwrap();
+
+        //None of the directories above the failed file can be processed
+        assert_eq!(
+            res.unwrap_err().file,
+            files::path_to_string(bad_dir.join("bad"))
+        );
+        assert!(bad_dir_exists);
+    }
+
+    #[derive(Default)]
+    struct KeepGoing {
+        failed: Mutex<Vec<String>>,
+    }
+
+    impl ParallelCallback for KeepGoing {
+        fn cb(&self, path: &Path) -> Result<bool, FileErr> {
+            FailOnBad.cb(path)
+        }
+
+        fn display_cb(&self, _path: &Path, _is_dir: bool) -> bool {
+            true
+        }
+
+        fn failed(&self, err: FileErr) -> Result<(), FileErr> {
+            self.failed.lock().unwrap().push(err.file);
+            Ok(())
+        }
+    }
+
+    impl RecursiveCallback for KeepGoing {
+        fn cb(&mut self, path: &Path) -> Result<bool, FileErr> {
+            ParallelCallback::cb(self, path)
+        }
+
+        fn display_cb(&mut self, _path: &Path, _is_dir: bool) -> bool {
+            true
+        }
+
+        fn failed(&mut self, err: FileErr) -> Result<(), FileErr> {
+            ParallelCallback::failed(self, err)
+        }
+    }
+
+    #[test]
+    fn test_recurse_on_paths_keep_going() {
+        for jobs in [1, 4] {
+            let dirname = generate_random_filename();
+            let base = Path::new(&dirname);
+            fs::create_dir_all(base.join("a").join("b")).unwrap();
+            fs::create_dir_all(base.join("c")).unwrap();
+            fs::write(base.join("a").join("b").join("bad"), b"data").unwrap();
+            fs::write(base.join("a").join("good"), b"data").unwrap();
+            fs::write(base.join("c").join("good"), b"data").unwrap();
+
+            let walk = files::WalkOptions {
+                recurse: true,
+                ..Default::default()
+            };
+            //A path given to the walk which can't be opened fails before anything is called on it
+            let missing = base.with_extension("missing");
+            let mut op = KeepGoing::default();
+            let res = match jobs {
+                1 => recurse_on_paths(&mut op, vec![&missing, base], &walk),
+                _ => recurse_on_paths_parallel(&op, vec![&missing, base], &walk, jobs),
+            };
+            let remaining = (
+                base.join("a").join("b").join("bad").exists(),
+                base.join("a").join("good").exists(),
+                base.join("c").exists(),
+            );
+            fs::remove_dir_all(&dirname).unwrap();
+
+            //Everything else is still processed, but the directories above the failed file are left alone, and only
+            //what was processed is counted
+            assert_eq!(res.unwrap(), 3);
+            assert_eq!(remaining, (true, false, false));
+            assert_eq!(
+                op.failed.into_inner().unwrap(),
+                vec![
+                    files::path_to_string(&missing),
+                    files::path_to_string(base.join("a").join("b").join("bad"))
+                ]
+            );
+        }
+    }
+
+    #[derive(Default)]
+    struct MountRecorder {
+        skipped: Mutex<Vec<std::path::PathBuf>>,
+    }
+
+    impl ParallelCallback for MountRecorder {
+        fn cb(&self, path: &Path) -> Result<bool, FileErr> {
+            Remover.cb(path)
+        }
+
+        fn display_cb(&self, _path: &Path, _is_dir: bool) -> bool {
+            true
+        }
+
+        fn mount_skipped(&self, path: &Path) {
+            self.skipped.lock().unwrap().push(path.to_path_buf());
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_recurse_on_paths_one_file_system() {
+        use std::os::unix::fs::MetadataExt;
+
+        //A followed link into /dev/shm stands in for a mount point, since tests can't mount anything
+        let shm = Path::new("/dev/shm");
+        let (Ok(shm_meta), Ok(cwd_meta)) = (fs::metadata(shm), fs::metadata(".")) else {
+            return;
+        };
+        if shm_meta.dev() == cwd_meta.dev() {
+            return;
+        }
+
+        let dirname = generate_random_filename();
+        let other = shm.join(&dirname);
+        fs::create_dir_all(Path::new(&dirname).join("sub")).unwrap();
+        fs::write(Path::new(&dirname).join("sub").join("file"), b"data").unwrap();
+        fs::create_dir(&other).unwrap();
+        fs::write(other.join("file"), b"data").unwrap();
+        std::os::unix::fs::symlink(&other, Path::new(&dirname).join("sub").join("mount")).unwrap();
+
+        let walk = files::WalkOptions {
+            recurse: true,
+            symlinks: files::SymlinkPolicy::Always,
+            one_file_system: true,
+            ..Default::default()
+        };
+        let recorder = MountRecorder::default();
+        let count = recurse_on_paths_parallel(&recorder, vec![Path::new(&dirname)], &walk, 2);
+        let other_kept = other.join("file").exists();
+        let file_exists = Path::new(&dirname).join("sub").join("file").exists();
+        let dir_exists = Path::new(&dirname).exists();
+        fs::remove_dir_all(&dirname).unwrap();
+        fs::remove_dir_all(&other).unwrap();
+
+        //Neither directory the mount point is in can be removed, so neither is passed to the callbacks
+        assert_eq!(count.unwrap(), 1);
+        assert!(other_kept);
+        assert!(!file_exists);
+        assert!(dir_exists);
+        assert_eq!(
+            *recorder.skipped.lock().unwrap(),
+            vec![Path::new(&dirname).join("sub").join("mount")]
+        );
+    }
+
+    #[test]
+    fn test_recurse_on_paths_parallel() {
+        let dirname = generate_random_filename();
+        for i in 0..4 {
+            let sub = Path::new(&dirname).join(i.to_string());
+            fs::create_dir_all(&sub).unwrap();
+            for j in 0..25 {
+                fs::write(sub.join(j.to_string()), b"data").unwrap();
+            }
+        }
+
+        let walk = files::WalkOptions {
+            recurse: true,
+            ..Default::default()
+        };
+        let count = recurse_on_paths_parallel(&Remover, vec![Path::new(&dirname)], &walk, 8);
+        let exists = Path::new(&dirname).exists();
+        if exists {
+            fs::remove_dir_all(&dirname).unwrap();
+        }
+
+        assert!(!exists);
+        assert_eq!(count.unwrap(), 4 * 25 + 4 + 1);
+    }
+
+    //Removes files relative to their directories, and swaps the directory called victim for a link to outside when
+    //it gets to the file called trigger
+    #[cfg(target_os = "linux")]
+    struct Swapper(std::path::PathBuf);
+
+    #[cfg(target_os = "linux")]
+    impl ParallelCallback for Swapper {
+        fn cb(&self, path: &Path) -> Result<bool, FileErr> {
+            panic!("{} was passed by path", path.display());
+        }
+
+        fn cb_at(&self, entry: &walker::EntryAt) -> Result<bool, FileErr> {
+            if entry.path().ends_with("trigger") {
+                let victim = entry.path().with_file_name("victim");
+                fs::remove_dir_all(&victim).unwrap();
+                std::os::unix::fs::symlink(&self.0, &victim).unwrap();
+            }
+            entry.remove().map_err(|e| FileErr::map(e, entry.path()))?;
+            Ok(true)
+        }
+
+        fn display_cb(&self, _path: &Path, _is_dir: bool) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_recurse_on_paths_parallel_symlink_swap() {
+        let base = generate_random_filename();
+        let tree = Path::new(&base).join("tree");
+        let outside = Path::new(&base).join("outside");
+        fs::create_dir_all(tree.join("victim")).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(tree.join("victim").join("file"), b"data").unwrap();
+        fs::write(tree.join("trigger"), b"data").unwrap();
+        fs::write(outside.join("file"), b"data").unwrap();
+
+        let walk = files::WalkOptions {
+            recurse: true,
+            ..Default::default()
+        };
+        //With one worker the files in a directory are processed before the directories in it are opened, so the
+        //victim has already been swapped by then
+        let swapper = Swapper(fs::canonicalize(&outside).unwrap());
+        let count = recurse_on_paths_parallel(&swapper, vec![tree.as_path()], &walk, 1);
+        let tree_exists = tree.exists();
+        let outside_kept = outside.join("file").exists();
+        fs::remove_dir_all(&base).unwrap();
+
+        assert_eq!(count.unwrap(), 3);
+        assert!(!tree_exists);
+        assert!(outside_kept);
+    }
+
+    #[test]
+    fn test_recurse_on_paths_filters() {
+        let walk = files::WalkOptions {
+            recurse: true,
+            filter: filter::Filter {
+                include: vec![glob::Pattern::new("*.log").unwrap()],
+                exclude: vec![glob::Pattern::new("keep").unwrap()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let fixture = || {
+            let dirname = generate_random_filename();
+            let base = Path::new(&dirname);
+            for dir in ["keep", "sub", "only"] {
+                fs::create_dir_all(base.join(dir)).unwrap();
+            }
+            for file in ["keep/a.log", "sub/x.log", "sub/y.txt", "only/z.log"] {
+                fs::write(base.join(file), b"data").unwrap();
+            }
+            dirname
+        };
+        //Only the directory which ends up empty goes, and nothing in the excluded one is touched
+        let remaining = |dirname: &str| {
+            let base = Path::new(dirname);
+            ["keep/a.log", "sub/x.log", "sub/y.txt", "only"].map(|p| base.join(p).exists())
+        };
+
+        let dirname = fixture();
+        let count = recurse_on_paths(&mut Remover, vec![Path::new(&dirname)], &walk);
+        let sequential = remaining(&dirname);
+        fs::remove_dir_all(&dirname).unwrap();
+
+        let dirname = fixture();
+        let parallel_count =
+            recurse_on_paths_parallel(&Remover, vec![Path::new(&dirname)], &walk, 4);
+        let parallel = remaining(&dirname);
+        fs::remove_dir_all(&dirname).unwrap();
+
+        assert_eq!(count.unwrap(), 3);
+        assert_eq!(sequential, [true, false, true, false]);
+        assert_eq!(parallel_count.unwrap(), 3);
+        assert_eq!(parallel, [true, false, true, false]);
+    }
+
+    #[test]
+    fn test_recurse_on_paths_protection() {
+        let dirname = generate_random_filename();
+        let sub = Path::new(&dirname).join("sub");
+        fs::create_dir_all(&sub).unwrap();
+        fs::write(sub.join("id.key"), b"data").unwrap();
+        fs::write(sub.join("file"), b"data").unwrap();
+
+        let mut walk = files::WalkOptions {
+            recurse: true,
+            protection: protect::Protection::builtin(),
+            ..Default::default()
+        };
+        //The working directory is protected, so nothing is touched even though the tree is fine
+        let refused = recurse_on_paths(
+            &mut Remover,
+            vec![Path::new(&dirname), Path::new(".")],
+            &walk,
+        );
+        let refused_kept = sub.join("file").exists();
+
+        walk.protection.add_pattern("*.key").unwrap();
+        let count = recurse_on_paths(&mut Remover, vec![Path::new(&dirname)], &walk);
+        let key_kept = sub.join("id.key").exists();
+        let file_exists = sub.join("file").exists();
+        fs::remove_dir_all(&dirname).unwrap();
+
+        assert!(refused.unwrap_err().protected().is_some());
+        assert!(refused_kept);
+        assert_eq!(count.unwrap(), 1);
+        assert!(key_kept);
+        assert!(!file_exists);
+    }
+
+    #[test]
+    fn test_recurse_on_paths_refuses_marked() {
+        let walk = files::WalkOptions {
+            recurse: true,
+            protection: protect::Protection::builtin(),
+            ..Default::default()
+        };
+        let fixture = || {
+            let dirname = generate_random_filename();
+            let project = Path::new(&dirname).join("sub").join("project");
+            fs::create_dir_all(&project).unwrap();
+            fs::write(project.join("file"), b"data").unwrap();
+            fs::write(Path::new(&dirname).join("sub").join("file"), b"data").unwrap();
+            protect::mark(&project, false).unwrap();
+            dirname
+        };
+        let remaining = |dirname: &str| {
+            let sub = Path::new(dirname).join("sub");
+            [sub.join("project").join("file"), sub.join("file")].map(|p| p.exists())
+        };
+        let marked = |res: Result<usize, FileErr>| match res.unwrap_err().protected() {
+            Some(protect::ProtectedPath {
+                kind: protect::ProtectedKind::Marked(marked),
+                ..
+            }) => marked.clone(),
+            other => panic!("expected the walk to be refused for a marked path, got {other:?}"),
+        };
+
+        let dirname = fixture();
+        let project = std::path::absolute(Path::new(&dirname).join("sub").join("project")).unwrap();
+        let res = recurse_on_paths(&mut Remover, vec![Path::new(&dirname)], &walk);
+        let sequential = remaining(&dirname);
+        fs::remove_dir_all(&dirname).unwrap();
+
+        let dirname = fixture();
+        let parallel_project =
+            std::path::absolute(Path::new(&dirname).join("sub").join("project")).unwrap();
+        let parallel_res = recurse_on_paths_parallel(&Remover, vec![Path::new(&dirname)], &walk, 4);
+        let parallel = remaining(&dirname);
+        fs::remove_dir_all(&dirname).unwrap();
+
+        //The marked directory is found before anything is removed, so nothing in the tree goes at all
+        assert_eq!(marked(res), vec![project]);
+        assert_eq!(sequential, [true, true]);
+        assert_eq!(marked(parallel_res), vec![parallel_project]);
+        assert_eq!(parallel, [true, true]);
+    }
+
+    #[test]
+    fn test_recurse_on_paths_reads_working_dir() {
+        struct Counter(usize);
+
+        impl RecursiveCallback for Counter {
+            fn cb(&mut self, _path: &Path) -> Result<bool, FileErr> {
+                self.0 += 1;
+                Ok(true)
+            }
+
+            fn display_cb(&mut self, _path: &Path, _is_dir: bool) -> bool {
+                true
+            }
+        }
+
+        //Walks which only read, like search, opt out of the protection
+        let walk = files::WalkOptions {
+            recurse: true,
+            protection: protect::Protection::none(),
+            filter: filter::Filter {
+                max_depth: Some(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut counter = Counter(0);
+        let cwd = std::env::current_dir().unwrap();
+        let count = recurse_on_paths(&mut counter, vec![Path::new("."), &cwd], &walk);
+
+        assert!(count.is_ok());
+        assert!(counter.0 > 0);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_recurse_on_paths_protects_by_default() {
+        struct Untouched;
+
+        impl RecursiveCallback for Untouched {
+            fn cb(&mut self, path: &Path) -> Result<bool, FileErr> {
+                panic!(
+                    "the walk should have been refused, but reached {}",
+                    path.display()
+                );
+            }
+
+            fn display_cb(&mut self, _path: &Path, _is_dir: bool) -> bool {
+                true
+            }
+        }
+
+        let walk = files::WalkOptions {
+            recurse: true,
+            ..Default::default()
+        };
+        let res = recurse_on_paths(&mut Untouched, vec![Path::new("/")], &walk);
+
+        assert_eq!(
+            res.unwrap_err().protected().map(|p| &p.kind),
+            Some(&protect::ProtectedKind::Root)
+        );
+    }
+}
@@ -0,0 +1,511 @@
+#[cfg(not(target_os = "linux"))]
+use std::fs;
+#[cfg(target_os = "linux")]
+use std::{
+    ffi::{CString, OsStr},
+    os::{
+        fd::{AsRawFd, OwnedFd, RawFd},
+        unix::ffi::OsStrExt,
+    },
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread,
+};
+
+#[cfg(not(target_os = "linux"))]
+use crate::files::{EntryKind, WalkState};
+#[cfg(target_os = "linux")]
+use crate::walker::{self, EntryAt, Found, Kind, WalkState};
+use crate::{
+    files::{self, WalkOptions},
+    FileErr, ParallelCallback,
+};
+
+//Most files are batched so that deleting huge trees doesn't mean taking the queue lock for every one of them,
+//but a handful of big files still get spread over all of the workers
+const MAX_FILE_BATCH: usize = 64;
+
+//A directory being walked. Its callbacks are run by whichever worker finishes the last thing inside it
+struct DirNode {
+    path: PathBuf,
+    parent: Option<Arc<DirNode>>,
+    #[cfg(target_os = "linux")]
+    at: DirAt,
+    //Subdirectories and batches of files still to be finished, plus one while the directory is being read
+    pending: AtomicUsize,
+    //Set if anything inside was skipped, since the directory then can't be processed either
+    partial: AtomicBool,
+    //Whether the filters select the directory itself, once everything inside it has been processed
+    selected: bool,
+}
+
+//On Linux the directory is held open, so that everything inside it is reached relative to it like in the serial
+//walker, and it's passed to the callbacks relative to its parent
+#[cfg(target_os = "linux")]
+struct DirAt {
+    dir: OwnedFd,
+    name: CString,
+    kind: Kind,
+}
+
+#[cfg(target_os = "linux")]
+impl DirNode {
+    fn fd(node: Option<&Arc<DirNode>>) -> RawFd {
+        node.map_or(libc::AT_FDCWD, |n| n.at.dir.as_raw_fd())
+    }
+}
+
+//A file to run the callbacks on, which on Linux comes with its name in the directory it's in
+#[cfg(target_os = "linux")]
+type FileEntry = (PathBuf, CString);
+#[cfg(not(target_os = "linux"))]
+type FileEntry = PathBuf;
+
+enum Task {
+    Dir(Arc<DirNode>, WalkState),
+    //An entry which may be a directory, along with its type. It's only opened by the worker which takes it, so
+    //that directories waiting in the queue don't each hold a descriptor open
+    #[cfg(target_os = "linux")]
+    Open(Arc<DirNode>, FileEntry, u8, WalkState),
+    Files(Option<Arc<DirNode>>, Vec<FileEntry>),
+}
+
+struct Queue {
+    tasks: Vec<Task>,
+    //Tasks which are queued or being run. The walk is over once there are none left
+    outstanding: usize,
+}
+
+struct Walk<'a, T: ParallelCallback> {
+    op: &'a T,
+    options: &'a WalkOptions,
+    jobs: usize,
+    queue: Mutex<Queue>,
+    available: Condvar,
+    stop: AtomicBool,
+    count: AtomicUsize,
+    error: Mutex<Option<FileErr>>,
+}
+
+///Walks the paths with a pool of worker threads, which all take directories to read and batches of files to
+/// process from a shared queue. A directory is only passed to the callbacks once everything inside it has been
+pub(crate) fn walk_parallel<T: ParallelCallback>(
+    op: &T,
+    paths: Vec<&Path>,
+    options: &WalkOptions,
+    jobs: usize,
+) -> Result<usize, FileErr> {
+    let walk = Walk {
+        op,
+        options,
+        jobs: jobs.max(1),
+        queue: Mutex::new(Queue {
+            tasks: Vec::new(),
+            outstanding: 0,
+        }),
+        available: Condvar::new(),
+        stop: AtomicBool::new(false),
+        count: AtomicUsize::new(0),
+        error: Mutex::new(None),
+    };
+
+    let mut top_level_files = Vec::new();
+    for path in files::unprotected_paths(paths, options, |e| op.failed(e))? {
+        if options.filter.prunes(path, 0) {
+            continue;
+        }
+        if let Some(file) = walk.push_top_level(path)? {
+            top_level_files.push(file);
+        }
+    }
+    walk.push_files(None, top_level_files);
+
+    thread::scope(|s| {
+        for _ in 0..walk.jobs {
+            s.spawn(|| walk.work());
+        }
+    });
+
+    match walk.error.into_inner().unwrap() {
+        Some(e) => Err(e),
+        None => Ok(walk.count.into_inner()),
+    }
+}
+
+impl<T: ParallelCallback> Walk<'_, T> {
+    fn push(&self, task: Task) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.tasks.push(task);
+        queue.outstanding += 1;
+        self.available.notify_one();
+    }
+
+    //Queues one of the paths given to the walk if it's a directory to descend into. Otherwise it's returned to be
+    //processed like a file if the filters select it
+    #[cfg(target_os = "linux")]
+    fn push_top_level(&self, path: &Path) -> Result<Option<FileEntry>, FileErr> {
+        let name = match CString::new(path.as_os_str().as_bytes()) {
+            Ok(name) => name,
+            Err(e) => {
+                self.op.failed(FileErr::map(e.into(), path))?;
+                return Ok(None);
+            }
+        };
+        let selected = files::selects_top_level(path, self.options);
+        match walker::open_top_level(&name, self.options) {
+            Ok(Some((dir, kind, state))) => {
+                let root = DirNode {
+                    path: path.to_path_buf(),
+                    parent: None,
+                    at: DirAt { dir, name, kind },
+                    pending: AtomicUsize::new(1),
+                    partial: AtomicBool::new(false),
+                    selected,
+                };
+                self.push(Task::Dir(Arc::new(root), state));
+                Ok(None)
+            }
+            Ok(None) if selected => Ok(Some((path.to_path_buf(), name))),
+            Ok(None) => Ok(None),
+            Err(e) => {
+                self.op.failed(FileErr::map(e, path))?;
+                Ok(None)
+            }
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn push_top_level(&self, path: &Path) -> Result<Option<FileEntry>, FileErr> {
+        let selected = files::selects_top_level(path, self.options);
+        if files::is_walkable_dir(path, self.options) {
+            let root = DirNode {
+                path: path.to_path_buf(),
+                parent: None,
+                pending: AtomicUsize::new(1),
+                partial: AtomicBool::new(false),
+                selected,
+            };
+            self.push(Task::Dir(
+                Arc::new(root),
+                WalkState::new(path, self.options),
+            ));
+            return Ok(None);
+        }
+        Ok(selected.then(|| path.to_path_buf()))
+    }
+
+    fn push_files(&self, parent: Option<Arc<DirNode>>, mut paths: Vec<FileEntry>) {
+        let batch = (paths.len() / self.jobs).clamp(1, MAX_FILE_BATCH);
+        while !paths.is_empty() {
+            let rest = paths.split_off(paths.len().saturating_sub(batch));
+            if let Some(parent) = &parent {
+                parent.pending.fetch_add(1, Ordering::Relaxed);
+            }
+            self.push(Task::Files(parent.clone(), rest));
+        }
+    }
+
+    fn work(&self) {
+        loop {
+            let task = {
+                let mut queue = self.queue.lock().unwrap();
+                loop {
+                    if self.stop.load(Ordering::Relaxed) || queue.outstanding == 0 {
+                        //Wakes up the other workers so that they can see it too
+                        self.available.notify_all();
+                        return;
+                    }
+                    match queue.tasks.pop() {
+                        Some(t) => break t,
+                        None => queue = self.available.wait(queue).unwrap(),
+                    }
+                }
+            };
+
+            match task {
+                Task::Dir(node, state) => self.read_dir(node, state),
+                #[cfg(target_os = "linux")]
+                Task::Open(parent, entry, d_type, state) => {
+                    self.open_dir(parent, entry, d_type, state)
+                }
+                Task::Files(parent, paths) => self.process_files(parent, paths),
+            }
+
+            let mut queue = self.queue.lock().unwrap();
+            queue.outstanding -= 1;
+            if queue.outstanding == 0 {
+                self.available.notify_all();
+            }
+        }
+    }
+
+    fn read_dir(&self, node: Arc<DirNode>, mut state: WalkState) {
+        if let Err(e) = self.queue_dir_contents(&node, &mut state) {
+            self.fail(e, Some(&node));
+        }
+        self.finish(node);
+    }
+
+    #[cfg(target_os = "linux")]
+    fn queue_dir_contents(
+        &self,
+        node: &Arc<DirNode>,
+        state: &mut WalkState,
+    ) -> Result<(), FileErr> {
+        let dir = node.at.dir.as_raw_fd();
+        state
+            .enter(&node.at.dir, self.options)
+            .map_err(|e| FileErr::map(e, &node.path))?;
+
+        let mut files = Vec::new();
+        for (name, d_type) in
+            walker::read_dir(&node.at.dir).map_err(|e| FileErr::map(e, &node.path))?
+        {
+            let path = node.path.join(OsStr::from_bytes(name.as_bytes()));
+            match walker::look_at(dir, &path, &name, d_type, self.options, state) {
+                Ok(d_type) if walker::may_be_dir(d_type, self.options) => {
+                    node.pending.fetch_add(1, Ordering::Relaxed);
+                    self.push(Task::Open(
+                        node.clone(),
+                        (path, name),
+                        d_type,
+                        state.clone(),
+                    ));
+                }
+                Ok(d_type) => {
+                    match walker::open_found(dir, &path, &name, d_type, self.options, state) {
+                        Found::Other => {
+                            let entry = EntryAt {
+                                path: &path,
+                                parent: dir,
+                                name: &name,
+                                kind: Kind::Other,
+                            };
+                            match walker::selects(&entry, d_type, self.options, state) {
+                                true => files.push((path, name)),
+                                false => node.partial.store(true, Ordering::Relaxed),
+                            }
+                        }
+                        found => self.not_walked(node, &path, found),
+                    }
+                }
+                Err(found) => self.not_walked(node, &path, found),
+            }
+        }
+        self.push_files(Some(node.clone()), files);
+
+        Ok(())
+    }
+
+    //Opens a directory found while reading its parent and reads it, unless it turns out to be something to
+    //process like a file or to skip
+    #[cfg(target_os = "linux")]
+    fn open_dir(
+        &self,
+        parent: Arc<DirNode>,
+        (path, name): FileEntry,
+        d_type: u8,
+        state: WalkState,
+    ) {
+        let dir = parent.at.dir.as_raw_fd();
+        let kind = match walker::open_found(dir, &path, &name, d_type, self.options, &state) {
+            Found::Dir(child, kind) => {
+                let entry = EntryAt {
+                    path: &path,
+                    parent: dir,
+                    name: &name,
+                    kind,
+                };
+                let node = DirNode {
+                    selected: walker::selects(&entry, d_type, self.options, &state),
+                    path,
+                    parent: Some(parent),
+                    at: DirAt {
+                        dir: child,
+                        name,
+                        kind,
+                    },
+                    pending: AtomicUsize::new(1),
+                    partial: AtomicBool::new(false),
+                };
+                //Finishing the directory finishes it in its parent too
+                return self.read_dir(Arc::new(node), state);
+            }
+            Found::Other => Kind::Other,
+            found => {
+                self.not_walked(&parent, &path, found);
+                return self.finish(parent);
+            }
+        };
+
+        let entry = EntryAt {
+            path: &path,
+            parent: dir,
+            name: &name,
+            kind,
+        };
+        if !walker::selects(&entry, d_type, self.options, &state) {
+            parent.partial.store(true, Ordering::Relaxed);
+        } else if !self.stop.load(Ordering::Relaxed) {
+            self.called(self.op.execute_callbacks_at(&entry), Some(&parent));
+        }
+        self.finish(parent);
+    }
+
+    //Deals with an entry which isn't walked or passed to the callbacks, which leaves its directory in place unless
+    //it's a directory already being walked
+    #[cfg(target_os = "linux")]
+    fn not_walked(&self, node: &Arc<DirNode>, path: &Path, found: Found) {
+        match found {
+            Found::Visited => return,
+            Found::Mount => self.op.mount_skipped(path),
+            Found::Protected(protected) => self.op.protected_skipped(&protected),
+            Found::Failed(e) => return self.fail(FileErr::map(e, path), Some(node)),
+            Found::Dir(..) | Found::Other | Found::Pruned => {}
+        }
+        node.partial.store(true, Ordering::Relaxed);
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn queue_dir_contents(
+        &self,
+        node: &Arc<DirNode>,
+        state: &mut WalkState,
+    ) -> Result<(), FileErr> {
+        state.enter(&node.path, self.options);
+
+        let mut file_paths = Vec::new();
+        for entry in fs::read_dir(&node.path).map_err(|e| FileErr::map(e, &node.path))? {
+            let entry = entry.map_err(|e| FileErr::map(e, &node.path))?;
+            let path = entry.path();
+            let file_type = entry.file_type().map_err(|e| FileErr::map(e, &path))?;
+            match files::entry_kind(&path, file_type, self.options, state) {
+                EntryKind::Dir => {
+                    let child = DirNode {
+                        selected: files::selects_entry(&path, file_type, self.options, state),
+                        path,
+                        parent: Some(node.clone()),
+                        pending: AtomicUsize::new(1),
+                        partial: AtomicBool::new(false),
+                    };
+                    node.pending.fetch_add(1, Ordering::Relaxed);
+                    self.push(Task::Dir(Arc::new(child), state.clone()));
+                }
+                EntryKind::Other if files::selects_entry(&path, file_type, self.options, state) => {
+                    file_paths.push(path)
+                }
+                EntryKind::Other | EntryKind::Pruned => node.partial.store(true, Ordering::Relaxed),
+                EntryKind::Visited => {}
+                EntryKind::Mount => {
+                    self.op.mount_skipped(&path);
+                    node.partial.store(true, Ordering::Relaxed);
+                }
+                EntryKind::Protected(protected) => {
+                    self.op.protected_skipped(&protected);
+                    node.partial.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+        self.push_files(Some(node.clone()), file_paths);
+
+        Ok(())
+    }
+
+    fn process_files(&self, parent: Option<Arc<DirNode>>, files: Vec<FileEntry>) {
+        for file in files {
+            if self.stop.load(Ordering::Relaxed) {
+                return;
+            }
+            #[cfg(target_os = "linux")]
+            let called = {
+                let (path, name) = file;
+                self.op.execute_callbacks_at(&EntryAt {
+                    path: &path,
+                    parent: DirNode::fd(parent.as_ref()),
+                    name: &name,
+                    kind: Kind::Other,
+                })
+            };
+            #[cfg(not(target_os = "linux"))]
+            let called = self.op.execute_callbacks(&file, false);
+            self.called(called, parent.as_ref());
+        }
+
+        if let Some(parent) = parent {
+            self.finish(parent);
+        }
+    }
+
+    //Marks one thing inside a directory as finished. The last one to finish runs the callbacks on the directory,
+    //and then counts as finishing something inside its parent
+    fn finish(&self, node: Arc<DirNode>) {
+        let mut next = Some(node);
+        while let Some(node) = next.take() {
+            if node.pending.fetch_sub(1, Ordering::AcqRel) != 1 || self.stop.load(Ordering::Relaxed)
+            {
+                return;
+            }
+
+            if node.partial.load(Ordering::Relaxed) || !node.selected {
+                if let Some(parent) = &node.parent {
+                    parent.partial.store(true, Ordering::Relaxed);
+                }
+            } else {
+                #[cfg(target_os = "linux")]
+                let called = self.op.execute_callbacks_at(&EntryAt {
+                    path: &node.path,
+                    parent: DirNode::fd(node.parent.as_ref()),
+                    name: &node.at.name,
+                    kind: node.at.kind,
+                });
+                #[cfg(not(target_os = "linux"))]
+                let called = self.op.execute_callbacks(&node.path, true);
+                if !self.called(called, node.parent.as_ref()) {
+                    return;
+                }
+            }
+            next = node.parent.clone();
+        }
+    }
+
+    //Deals with what the callbacks returned for a path, counting it if they succeeded. Returns false if they
+    //stopped the walk
+    fn called(&self, called: Result<bool, FileErr>, parent: Option<&Arc<DirNode>>) -> bool {
+        if called.is_ok() {
+            self.count.fetch_add(1, Ordering::Relaxed);
+        }
+        match called {
+            Ok(true) => true,
+            Ok(false) => {
+                self.stop.store(true, Ordering::Relaxed);
+                false
+            }
+            Err(e) => {
+                self.fail(e, parent);
+                true
+            }
+        }
+    }
+
+    //Unless the operation carries on from it, the first error stops any further work from being started and is the
+    //one returned. Otherwise the directory the failed file was in is skipped
+    fn fail(&self, e: FileErr, parent: Option<&Arc<DirNode>>) {
+        match self.op.failed(e) {
+            Ok(()) => {
+                if let Some(parent) = parent {
+                    parent.partial.store(true, Ordering::Relaxed);
+                }
+            }
+            Err(e) => {
+                self.stop.store(true, Ordering::Relaxed);
+                self.error.lock().unwrap().get_or_insert(e);
+                self.available.notify_all();
+            }
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use std::{
+    fmt::Display,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+///What is recorded about a file when it's planned for, so that it can be told apart from anything which later
+/// replaces or changes it
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FileIdentity {
+    pub inode: u64,
+    pub size: u64,
+    pub mtime: DateTime<Utc>,
+}
+
+impl FileIdentity {
+    ///Reads the identity of a path without following it if it's a symlink
+    pub fn of(path: &Path) -> io::Result<FileIdentity> {
+        let metadata = fs::symlink_metadata(path)?;
+        Ok(FileIdentity {
+            inode: inode(&metadata),
+            size: metadata.len(),
+            mtime: metadata.modified()?.into(),
+        })
+    }
+}
+
+#[cfg(unix)]
+fn inode(metadata: &fs::Metadata) -> u64 {
+    std::os::unix::fs::MetadataExt::ino(metadata)
+}
+
+//Windows only exposes file indexes through unstable APIs, so the size and modification time have to do
+#[cfg(not(unix))]
+fn inode(_metadata: &fs::Metadata) -> u64 {
+    0
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlanAction {
+    Trash,
+    Delete,
+    Shred,
+    Restore,
+    Purge,
+}
+
+///One resolved target of a plan and what is to be done to it
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlanEntry {
+    pub action: PlanAction,
+    ///Absolute path of the target. For items in the trash this is where the item was trashed from
+    pub path: PathBuf,
+    ///Id of the item in the trash, for restores and purges
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trash_id: Option<String>,
+    ///None if the file couldn't be read when planning, or doesn't exist on disk like items in some trashes.
+    /// Entries without one are only checked for existence
+    pub identity: Option<FileIdentity>,
+}
+
+///Why a plan entry is refused
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stale {
+    Missing,
+    Changed,
+    ///A directory which has a stale entry inside it, so it can't be acted on as planned either
+    ContainsStale,
+    ///A trash item whose backing file can't be found on this platform, so there's nothing to check it against
+    Unverifiable,
+}
+
+impl Display for Stale {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stale::Missing => write!(f, "no longer exists"),
+            Stale::Changed => write!(f, "has changed since the plan was made"),
+            Stale::ContainsStale => write!(f, "contains a stale entry"),
+            Stale::Unverifiable => write!(f, "can't be checked against the trash on this platform"),
+        }
+    }
+}
+
+impl PlanEntry {
+    ///Checks that the file on disk backing the entry is still the one that was planned for. This is the entry's
+    /// path, or the file backing it in the trash for restores and purges
+    pub fn verify(&self, current: &Path) -> Result<(), Stale> {
+        match (FileIdentity::of(current), &self.identity) {
+            (Err(_), _) => Err(Stale::Missing),
+            (Ok(found), Some(planned)) if found != *planned => Err(Stale::Changed),
+            _ => Ok(()),
+        }
+    }
+}
+
+///A reviewable list of what an operation would do, which can be edited and then applied
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Plan {
+    ///The command line the plan was made with, which the options it's applied with come from
+    pub args: Vec<String>,
+    ///Directory the plan was made from, which any relative paths in the arguments are relative to
+    pub cwd: PathBuf,
+    pub created: DateTime<Utc>,
+    pub entries: Vec<PlanEntry>,
+}
+
+impl Plan {
+    pub fn read(path: &Path) -> io::Result<Plan> {
+        let contents = fs::read(path)?;
+        Ok(serde_json::from_slice(&contents)?)
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)
+    }
+
+    ///Checks every entry, returning the ones which are stale along with why. Directories containing a stale
+    /// entry are stale too, since they can't be removed once it's been left in place. The backing path of an
+    /// entry is found with the given function, which returns why an entry is stale instead if it can't be found,
+    /// like trash items that are gone
+    pub fn stale_entries(
+        &self,
+        backing_path: impl Fn(&PlanEntry) -> Result<PathBuf, Stale>,
+    ) -> Vec<(usize, Stale)> {
+        let mut stale: Vec<(usize, Stale)> = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter_map(|(i, entry)| {
+                let res = backing_path(entry).and_then(|path| entry.verify(&path));
+                res.err().map(|reason| (i, reason))
+            })
+            .collect();
+
+        let stale_paths: Vec<PathBuf> = stale
+            .iter()
+            .map(|(i, _)| self.entries[*i].path.clone())
+            .collect();
+        for (i, entry) in self.entries.iter().enumerate() {
+            let contains_stale = stale_paths
+                .iter()
+                .any(|p| p != &entry.path && p.starts_with(&entry.path));
+            if contains_stale && !stale.iter().any(|(s, _)| *s == i) {
+                stale.push((i, Stale::ContainsStale));
+            }
+        }
+        stale.sort_by_key(|(i, _)| *i);
+
+        stale
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::files::test_util::generate_random_filename;
+
+    #[test]
+    fn test_plan_stale_entries() {
+        let dirname = generate_random_filename();
+        let dir = std::path::absolute(&dirname).unwrap();
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub").join("changed"), b"data").unwrap();
+        fs::write(dir.join("kept"), b"data").unwrap();
+        fs::write(dir.join("removed"), b"data").unwrap();
+        fs::write(dir.join("unrecorded"), b"data").unwrap();
+
+        let entry = |path: PathBuf| PlanEntry {
+            action: PlanAction::Delete,
+            identity: Some(FileIdentity::of(&path).unwrap()),
+            path,
+            trash_id: None,
+        };
+        let plan = Plan {
+            args: vec!["rrc".to_string(), "delete".to_string(), dirname.clone()],
+            cwd: std::env::current_dir().unwrap(),
+            created: Utc::now(),
+            entries: vec![
+                entry(dir.join("sub").join("changed")),
+                entry(dir.join("sub")),
+                entry(dir.join("kept")),
+                entry(dir.join("removed")),
+                entry(dir.clone()),
+                PlanEntry {
+                    action: PlanAction::Delete,
+                    path: dir.join("unrecorded"),
+                    trash_id: None,
+                    identity: None,
+                },
+            ],
+        };
+
+        let plan_path = PathBuf::from(format!("{dirname}.json"));
+        plan.write(&plan_path).unwrap();
+        let read = Plan::read(&plan_path);
+        //Rewriting a file leaves the directory it's in alone, but removing one changes the directory too
+        fs::write(dir.join("sub").join("changed"), b"more data").unwrap();
+        fs::remove_file(dir.join("removed")).unwrap();
+        fs::remove_file(dir.join("unrecorded")).unwrap();
+        let stale = plan.stale_entries(|e| Ok(e.path.clone()));
+        let unverifiable = plan.stale_entries(|e| match e.path == dir {
+            true => Err(Stale::Unverifiable),
+            false => Ok(e.path.clone()),
+        });
+        fs::remove_dir_all(&dir).unwrap();
+        fs::remove_file(&plan_path).unwrap();
+
+        assert_eq!(read.unwrap(), plan);
+        assert_eq!(
+            stale,
+            vec![
+                (0, Stale::Changed),
+                (1, Stale::ContainsStale),
+                (3, Stale::Missing),
+                (4, Stale::Changed),
+                (5, Stale::Missing)
+            ]
+        );
+        assert_eq!(unverifiable[3], (4, Stale::Unverifiable));
+    }
+}
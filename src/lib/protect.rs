@@ -0,0 +1,693 @@
+use std::{
+    env,
+    error::Error,
+    fmt::Display,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+#[cfg(target_os = "linux")]
+use std::{
+    ffi::{CStr, CString, OsStr},
+    os::{
+        fd::{AsRawFd, OwnedFd, RawFd},
+        unix::ffi::OsStrExt,
+    },
+};
+
+use glob::{Pattern, PatternError};
+
+use crate::{files, filter, FileErr, RecursiveCallback};
+
+///A directory holding a file with this name is protected
+pub const MARKER_FILE: &str = ".rrc-protect";
+///Any file with this extended attribute set is protected
+pub const MARKER_XATTR: &str = "user.rrc.protect";
+
+///Why a path is protected
+#[derive(Clone, Debug, PartialEq)]
+pub enum ProtectedKind {
+    Root,
+    System,
+    Home,
+    Trash,
+    WorkingDir,
+    ///Matched a user configured pattern, which is kept here as it was given
+    Pattern(String),
+    ///Is or contains paths marked with a marker file or attribute, which are all listed here
+    Marked(Vec<PathBuf>),
+}
+
+impl Display for ProtectedKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProtectedKind::Root => write!(f, "the root directory"),
+            ProtectedKind::System => write!(f, "a system directory"),
+            ProtectedKind::Home => write!(f, "the home directory"),
+            ProtectedKind::Trash => write!(f, "a trash directory"),
+            ProtectedKind::WorkingDir => {
+                write!(f, "the working directory or one of its parents")
+            }
+            ProtectedKind::Pattern(p) => write!(f, "the protected pattern {p}"),
+            ProtectedKind::Marked(_) => write!(f, "a marked path"),
+        }
+    }
+}
+
+///A path an operation was refused on, or skipped while walking a tree
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProtectedPath {
+    ///The path as it was resolved for the check
+    pub path: PathBuf,
+    ///The protected path it is or contains
+    pub protected: PathBuf,
+    pub kind: ProtectedKind,
+}
+
+impl Display for ProtectedPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let path = files::path_to_string(&self.path);
+        match &self.kind {
+            ProtectedKind::Pattern(_) => write!(f, "{path} is matched by {}", self.kind),
+            ProtectedKind::Marked(marked) if marked.len() == 1 && marked[0] == self.path => {
+                write!(f, "{path} is marked as protected")
+            }
+            ProtectedKind::Marked(marked) => write!(
+                f,
+                "{path} contains {} path(s) marked as protected",
+                marked.len()
+            ),
+            kind if self.path == self.protected => write!(f, "{path} is protected as {kind}"),
+            kind => write!(
+                f,
+                "{path} is protected as it contains {kind} {}",
+                files::path_to_string(&self.protected)
+            ),
+        }
+    }
+}
+
+impl Error for ProtectedPath {}
+
+//Directories directly under the root which hold the system, as opposed to anything a user might have made there
+#[cfg(unix)]
+const SYSTEM_DIRS: &[&str] = &[
+    "/bin",
+    "/boot",
+    "/dev",
+    "/etc",
+    "/home",
+    "/lib",
+    "/lib32",
+    "/lib64",
+    "/libx32",
+    "/opt",
+    "/proc",
+    "/root",
+    "/run",
+    "/sbin",
+    "/snap",
+    "/srv",
+    "/sys",
+    "/tmp",
+    "/usr",
+    "/var",
+    "/Applications",
+    "/Library",
+    "/System",
+    "/Users",
+    "/Volumes",
+    "/private",
+];
+
+///Paths which the walkers refuse to run operations on. Every walk checks the paths it's given against the
+/// protected directories and any configured patterns before touching anything, and anything inside the tree
+/// matching a pattern is skipped. A path is refused if it is a protected directory or contains one, so that
+/// removing it would take the protected directory with it, and the same goes for marked paths. The default is
+/// builtin, so that nothing removing files goes without it by accident, and walks which only read opt out with
+/// none
+#[derive(Clone, Debug)]
+pub struct Protection {
+    disabled: bool,
+    paths: Vec<(PathBuf, ProtectedKind)>,
+    patterns: Vec<Pattern>,
+    //Protected paths inside these, and anything inside them matching a pattern, are let through
+    allowed: Vec<PathBuf>,
+    //Relative paths are resolved against the directory the protection was set up in, so that checking them
+    //doesn't mean asking for it again for every entry of a walk
+    cwd: PathBuf,
+    //Whether the trees given to a walk are searched for marked paths before anything is done to them. Turned off
+    //for the search itself
+    scan_marked: bool,
+}
+
+impl Default for Protection {
+    fn default() -> Self {
+        Protection::builtin()
+    }
+}
+
+impl Protection {
+    ///The root, top level system directories, the home directory, the trash directories and the working
+    /// directory along with its parents
+    pub fn builtin() -> Protection {
+        let cwd = env::current_dir().unwrap_or_default();
+        let mut paths = Vec::new();
+
+        #[cfg(unix)]
+        {
+            paths.push((PathBuf::from("/"), ProtectedKind::Root));
+            paths.extend(
+                SYSTEM_DIRS
+                    .iter()
+                    .map(|d| (PathBuf::from(d), ProtectedKind::System)),
+            );
+        }
+        #[cfg(windows)]
+        {
+            for var in [
+                "SystemDrive",
+                "SystemRoot",
+                "ProgramFiles",
+                "ProgramFiles(x86)",
+            ] {
+                if let Some(dir) = env::var_os(var) {
+                    let kind = match var {
+                        "SystemDrive" => ProtectedKind::Root,
+                        _ => ProtectedKind::System,
+                    };
+                    let mut dir = PathBuf::from(dir);
+                    //The drive on its own is relative to the working directory on that drive
+                    dir.push(std::path::MAIN_SEPARATOR_STR);
+                    paths.push((dir, kind));
+                }
+            }
+        }
+
+        if let Some(home) = home_dir() {
+            paths.push((home.clone(), ProtectedKind::Home));
+            paths.extend(
+                trash_dirs(&home)
+                    .into_iter()
+                    .map(|d| (d, ProtectedKind::Trash)),
+            );
+        }
+        paths.extend(
+            cwd.ancestors()
+                .map(|d| (d.to_path_buf(), ProtectedKind::WorkingDir)),
+        );
+
+        //Links such as a home directory under /usr/home are protected both where they point and where they are
+        let canonical: Vec<(PathBuf, ProtectedKind)> = paths
+            .iter()
+            .filter_map(|(p, kind)| match fs::canonicalize(p) {
+                Ok(c) if &c != p => Some((c, kind.clone())),
+                _ => None,
+            })
+            .collect();
+        paths.extend(canonical);
+
+        Protection {
+            disabled: false,
+            paths,
+            patterns: Vec::new(),
+            allowed: Vec::new(),
+            cwd,
+            scan_marked: true,
+        }
+    }
+
+    ///Protects nothing at all
+    pub fn none() -> Protection {
+        Protection {
+            disabled: true,
+            paths: Vec::new(),
+            patterns: Vec::new(),
+            allowed: Vec::new(),
+            cwd: PathBuf::new(),
+            scan_marked: false,
+        }
+    }
+
+    ///Adds a glob pattern which absolute paths are matched against. A pattern without any separators is matched
+    /// against file names instead, and a leading ~ stands for the home directory
+    pub fn add_pattern(&mut self, pattern: &str) -> Result<(), PatternError> {
+        let expanded = match (pattern.strip_prefix("~/"), home_dir()) {
+            (Some(rest), Some(home)) => format!("{}/{rest}", files::path_to_string(home)),
+            _ => pattern.to_string(),
+        };
+        self.patterns.push(Pattern::new(&expanded)?);
+        Ok(())
+    }
+
+    ///Lets operations run on a path even though it's protected, or contains protected paths
+    pub fn allow(&mut self, path: &Path) {
+        let resolved = self.resolve(path);
+        self.allowed.push(resolved);
+    }
+
+    ///Checks a path given to an operation, returning what it would have removed that is protected. If the path
+    /// is a link which is going to be followed, where it points is checked too
+    pub fn check(&self, path: &Path, follow: bool) -> Result<(), ProtectedPath> {
+        if self.disabled {
+            return Ok(());
+        }
+        self.check_resolved(self.resolve(path))?;
+        match follow && fs::symlink_metadata(path).is_ok_and(|m| m.is_symlink()) {
+            true => self.check_followed(path),
+            false => Ok(()),
+        }
+    }
+
+    ///Checks a path found while walking a tree. The built in paths can only be reached by following a link from
+    /// a path which has already been checked, so only the patterns are looked at
+    pub fn check_entry(&self, path: &Path) -> Result<(), ProtectedPath> {
+        if self.disabled || self.patterns.is_empty() {
+            return Ok(());
+        }
+        self.check_patterns(&self.cwd.join(path))
+    }
+
+    ///Checks where a link to a directory, which a walk is about to descend into, points
+    pub fn check_followed(&self, path: &Path) -> Result<(), ProtectedPath> {
+        if self.disabled {
+            return Ok(());
+        }
+        match fs::canonicalize(path) {
+            Ok(target) => self.check_resolved(target),
+            Err(_) => Ok(()),
+        }
+    }
+
+    ///Checks whether a path given to an operation is marked itself, or is a marker file. Marked paths inside it
+    /// are looked for by check_walk
+    pub fn check_marked(&self, path: &Path) -> Result<(), ProtectedPath> {
+        if self.disabled {
+            return Ok(());
+        }
+        let resolved = self.resolve(path);
+        //Removing the marker file is as good as removing the protection
+        let marked = resolved.file_name() == Some(MARKER_FILE.as_ref()) || is_marked(path);
+        match marked && !self.is_allowed(&resolved) {
+            true => Err(ProtectedPath {
+                path: resolved.clone(),
+                protected: resolved.clone(),
+                kind: ProtectedKind::Marked(vec![resolved]),
+            }),
+            false => Ok(()),
+        }
+    }
+
+    ///Checks a path found while walking a tree for markers. The walker works out whether it's marked, since it
+    /// can do that relative to the directory it's reading, and only does so if markers are being honoured
+    pub(crate) fn check_marked_entry(
+        &self,
+        path: &Path,
+        marked: impl FnOnce() -> bool,
+    ) -> Result<(), ProtectedPath> {
+        if self.disabled {
+            return Ok(());
+        }
+        let absolute = self.cwd.join(path);
+        match !self.is_allowed(&absolute) && marked() {
+            true => Err(ProtectedPath {
+                path: absolute.clone(),
+                protected: absolute.clone(),
+                kind: ProtectedKind::Marked(vec![absolute]),
+            }),
+            false => Ok(()),
+        }
+    }
+
+    ///Looks for marked paths anywhere in the tree a walk with the given options would go through, so that the
+    /// walk can be refused with every one of them listed before anything in it is removed. The tree is walked
+    /// without changing anything first. Markers which turn up after this are still skipped by the walk itself
+    pub(crate) fn check_walk(
+        &self,
+        path: &Path,
+        options: &files::WalkOptions,
+    ) -> Result<(), ProtectedPath> {
+        if self.disabled || !self.scan_marked || !options.recurse {
+            return Ok(());
+        }
+        let options = files::WalkOptions {
+            protection: Protection {
+                scan_marked: false,
+                ..self.clone()
+            },
+            ..options.clone()
+        };
+        let mut finder = MarkFinder(Vec::new());
+        //Anything which can't be read is left for the operation itself to fail on
+        let _ = crate::recurse_on_paths(&mut finder, vec![path], &options);
+
+        let resolved = self.resolve(path);
+        match finder.0.first() {
+            Some(first) => Err(ProtectedPath {
+                path: resolved,
+                protected: first.clone(),
+                kind: ProtectedKind::Marked(finder.0),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    ///Looks for marked paths at or anywhere under a path which is going to be moved whole, like to the trash,
+    /// without a walk to find them on the way
+    pub fn check_tree(&self, path: &Path) -> Result<(), ProtectedPath> {
+        //Everything goes along with the path, so nothing is skipped for matching a pattern
+        let protection = Protection {
+            patterns: Vec::new(),
+            ..self.clone()
+        };
+        let options = files::WalkOptions {
+            recurse: true,
+            protection: Protection::none(),
+            ..Default::default()
+        };
+        protection.check_walk(path, &options)
+    }
+
+    fn check_resolved(&self, path: PathBuf) -> Result<(), ProtectedPath> {
+        let found = self
+            .paths
+            .iter()
+            .find(|(p, _)| p.starts_with(&path) && !self.is_allowed(p));
+        if let Some((protected, kind)) = found {
+            return Err(ProtectedPath {
+                path,
+                protected: protected.clone(),
+                kind: kind.clone(),
+            });
+        }
+        self.check_patterns(&path)
+    }
+
+    fn check_patterns(&self, path: &Path) -> Result<(), ProtectedPath> {
+        match filter::matching_pattern(&self.patterns, path) {
+            Some(p) if !self.is_allowed(path) => Err(ProtectedPath {
+                path: path.to_path_buf(),
+                protected: path.to_path_buf(),
+                kind: ProtectedKind::Pattern(p.as_str().to_string()),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn is_allowed(&self, path: &Path) -> bool {
+        self.allowed.iter().any(|a| path.starts_with(a))
+    }
+
+    //Makes a path absolute and resolves any links leading up to it. The path itself isn't resolved, since a link
+    //is judged by where it is rather than what it points at
+    fn resolve(&self, path: &Path) -> PathBuf {
+        let absolute = self.cwd.join(path);
+        let resolved = match (absolute.parent(), absolute.file_name()) {
+            (Some(parent), Some(name)) => fs::canonicalize(parent).map(|p| p.join(name)),
+            _ => fs::canonicalize(&absolute),
+        };
+        resolved.unwrap_or(absolute)
+    }
+}
+
+//Collects the marked paths a walk skips, without doing anything to the rest
+struct MarkFinder(Vec<PathBuf>);
+
+impl RecursiveCallback for MarkFinder {
+    fn cb(&mut self, _path: &Path) -> Result<bool, FileErr> {
+        Ok(true)
+    }
+
+    fn display_cb(&mut self, _path: &Path, _is_dir: bool) -> bool {
+        true
+    }
+
+    fn protected_skipped(&mut self, protected: &ProtectedPath) {
+        if let ProtectedKind::Marked(marked) = &protected.kind {
+            self.0.extend_from_slice(marked);
+        }
+    }
+
+    fn failed(&mut self, err: FileErr) -> Result<(), FileErr> {
+        if let Some(protected) = err.protected() {
+            self.protected_skipped(&protected.clone());
+        }
+        Ok(())
+    }
+}
+
+///Returns whether a path is marked as protected, by a marker file inside it or the marker attribute
+pub fn is_marked(path: &Path) -> bool {
+    fs::symlink_metadata(path.join(MARKER_FILE)).is_ok() || has_marker_xattr(path)
+}
+
+///Marks a path as protected. Directories get a marker file unless the attribute is asked for, and anything
+/// else can only be marked with the attribute
+pub fn mark(path: &Path, xattr: bool) -> io::Result<()> {
+    if !xattr && fs::symlink_metadata(path)?.is_dir() {
+        fs::write(path.join(MARKER_FILE), b"")
+    } else {
+        set_marker_xattr(path)
+    }
+}
+
+///Removes every marker from a path, returning whether there were any
+pub fn unmark(path: &Path) -> io::Result<bool> {
+    let mut removed = has_marker_xattr(path);
+    if removed {
+        remove_marker_xattr(path)?;
+    }
+    match fs::remove_file(path.join(MARKER_FILE)) {
+        Ok(()) => removed = true,
+        Err(e)
+            if matches!(
+                e.kind(),
+                io::ErrorKind::NotFound | io::ErrorKind::NotADirectory
+            ) => {}
+        Err(e) => return Err(e),
+    }
+    Ok(removed)
+}
+
+#[cfg(target_os = "linux")]
+fn xattr_call(
+    path: &Path,
+    call: impl FnOnce(*const libc::c_char, *const libc::c_char) -> isize,
+) -> io::Result<isize> {
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let name = CString::new(MARKER_XATTR)?;
+    match call(path.as_ptr(), name.as_ptr()) {
+        -1 => Err(io::Error::last_os_error()),
+        n => Ok(n),
+    }
+}
+
+//The l variants are used throughout, so a link is marked itself rather than whatever it points at
+#[cfg(target_os = "linux")]
+fn has_marker_xattr(path: &Path) -> bool {
+    xattr_call(path, |p, n| unsafe {
+        libc::lgetxattr(p, n, std::ptr::null_mut(), 0)
+    })
+    .is_ok()
+}
+
+//Whether a directory a walk has opened holds a marker file or has the marker attribute, checked through the
+//descriptor so that nothing can be swapped in under the walk
+#[cfg(target_os = "linux")]
+pub(crate) fn is_marked_dir(dir: &OwnedFd) -> bool {
+    let (Ok(marker), Ok(name)) = (CString::new(MARKER_FILE), CString::new(MARKER_XATTR)) else {
+        return false;
+    };
+    let mut stat: libc::stat64 = unsafe { std::mem::zeroed() };
+    let fd = dir.as_raw_fd();
+    let has_file =
+        unsafe { libc::fstatat64(fd, marker.as_ptr(), &mut stat, libc::AT_SYMLINK_NOFOLLOW) } == 0;
+    has_file || unsafe { libc::fgetxattr(fd, name.as_ptr(), std::ptr::null_mut(), 0) } >= 0
+}
+
+//Whether an entry of a directory a walk has open has the marker attribute. There's no call which takes the name
+//relative to the directory, so it goes through the directory's descriptor in /proc, falling back to the path if
+//that can't be found
+#[cfg(target_os = "linux")]
+pub(crate) fn has_marker_xattr_at(dir: RawFd, name: &CStr, path: &Path) -> bool {
+    let mut at = format!("/proc/self/fd/{dir}/").into_bytes();
+    at.extend_from_slice(name.to_bytes());
+    match xattr_call(Path::new(OsStr::from_bytes(&at)), |p, n| unsafe {
+        libc::lgetxattr(p, n, std::ptr::null_mut(), 0)
+    }) {
+        Ok(_) => true,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => has_marker_xattr(path),
+        Err(_) => false,
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_marker_xattr(path: &Path) -> io::Result<()> {
+    xattr_call(path, |p, n| unsafe {
+        libc::lsetxattr(p, n, b"1".as_ptr().cast(), 1, 0) as isize
+    })
+    .map(|_| ())
+}
+
+#[cfg(target_os = "linux")]
+fn remove_marker_xattr(path: &Path) -> io::Result<()> {
+    xattr_call(path, |p, n| unsafe { libc::lremovexattr(p, n) as isize }).map(|_| ())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn has_marker_xattr(_path: &Path) -> bool {
+    false
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_marker_xattr(_path: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "marking with an extended attribute is only supported on Linux",
+    ))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn remove_marker_xattr(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+fn home_dir() -> Option<PathBuf> {
+    let var = match cfg!(windows) {
+        true => "USERPROFILE",
+        false => "HOME",
+    };
+    env::var_os(var)
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+}
+
+//The home trash, along with the per user and shared trashes at the top of every mounted filesystem
+fn trash_dirs(home: &Path) -> Vec<PathBuf> {
+    let mut dirs = vec![
+        match env::var_os("XDG_DATA_HOME").filter(|d| !d.is_empty()) {
+            Some(dir) => PathBuf::from(dir).join("Trash"),
+            None => home.join(".local").join("share").join("Trash"),
+        },
+    ];
+
+    #[cfg(target_os = "linux")]
+    if let Ok(mountinfo) = fs::read_to_string("/proc/self/mountinfo") {
+        let uid = unsafe { libc::getuid() };
+        for mount in crate::filesystem::parse_mountinfo(&mountinfo) {
+            dirs.push(mount.mount_point.join(".Trash"));
+            dirs.push(mount.mount_point.join(format!(".Trash-{uid}")));
+        }
+    }
+
+    dirs
+}
+
+///Where protected patterns are read from when no other file is given. This is rrc/protected in the user's
+/// config directory, or None if there isn't one
+pub fn default_patterns_path() -> Option<PathBuf> {
+    let config_dir = match env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None if cfg!(windows) => PathBuf::from(env::var_os("APPDATA")?),
+        None => home_dir()?.join(".config"),
+    };
+    Some(config_dir.join("rrc").join("protected"))
+}
+
+///Reads protected patterns from a file with one on each line. Blank lines and lines starting with # are skipped
+pub fn read_patterns(path: &Path) -> io::Result<Vec<String>> {
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::files::test_util::{generate_random_filename, temp_dir};
+
+    #[test]
+    fn test_protection_refuses_builtin_paths() {
+        let protection = Protection::builtin();
+        let cwd = env::current_dir().unwrap();
+
+        assert!(protection.check(Path::new(".."), false).is_err());
+        assert_eq!(
+            protection.check(&cwd, false).unwrap_err().kind,
+            ProtectedKind::WorkingDir
+        );
+        #[cfg(unix)]
+        {
+            let err = protection.check(Path::new("/"), false).unwrap_err();
+            assert_eq!(err.kind, ProtectedKind::Root);
+            assert!(protection.check(Path::new("/usr/"), false).is_err());
+        }
+
+        let dirname = temp_dir();
+        let inside = protection.check(Path::new(&dirname), false);
+        fs::remove_dir(&dirname).unwrap();
+        assert!(inside.is_ok());
+
+        let mut allowed = Protection::builtin();
+        allowed.allow(&cwd);
+        assert!(allowed.check(&cwd, false).is_ok());
+        assert!(allowed.check(Path::new(".."), false).is_err());
+        assert!(Protection::none().check(Path::new(".."), false).is_ok());
+    }
+
+    #[test]
+    fn test_protection_patterns() {
+        let mut protection = Protection::builtin();
+        protection.add_pattern("*.key").unwrap();
+        protection.add_pattern("/srv/keep/**").unwrap();
+        assert!(protection.add_pattern("[").is_err());
+
+        assert!(protection.check_entry(Path::new("dir/id.key")).is_err());
+        assert!(protection.check_entry(Path::new("dir/id.pub")).is_ok());
+        assert!(protection.check_entry(Path::new("/srv/keep/a/b")).is_err());
+        assert!(protection.check_entry(Path::new("/srv/other/a")).is_ok());
+
+        protection.allow(Path::new("/srv/keep"));
+        assert!(protection.check_entry(Path::new("/srv/keep/a/b")).is_ok());
+    }
+
+    #[test]
+    fn test_protection_markers() {
+        let dirname = generate_random_filename();
+        let project = Path::new(&dirname).join("project");
+        fs::create_dir_all(&project).unwrap();
+        fs::write(Path::new(&dirname).join("file"), b"data").unwrap();
+        let protection = Protection::builtin();
+
+        mark(&project, false).unwrap();
+        let marker_exists = project.join(MARKER_FILE).exists();
+        let contains = protection.check_tree(Path::new(&dirname));
+        let only_inside = protection.check_marked(Path::new(&dirname));
+        let marked = protection.check_marked(&project);
+        let marker = protection.check_marked(&project.join(MARKER_FILE));
+        let mut allowed = Protection::builtin();
+        allowed.allow(&project);
+        let allowed_res = allowed.check_tree(Path::new(&dirname));
+        let unmarked = unmark(&project).unwrap();
+        let unmarked_again = unmark(&project).unwrap();
+        let after = protection.check_tree(Path::new(&dirname));
+        fs::remove_dir_all(&dirname).unwrap();
+
+        assert!(marker_exists);
+        let err = contains.unwrap_err();
+        assert_eq!(
+            err.kind,
+            ProtectedKind::Marked(vec![env::current_dir().unwrap().join(&project)])
+        );
+        assert!(only_inside.is_ok());
+        assert!(marked.is_err());
+        assert!(marker.is_err());
+        assert!(allowed_res.is_ok());
+        assert!(unmarked);
+        assert!(!unmarked_again);
+        assert!(after.is_ok());
+    }
+}
@@ -0,0 +1,301 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    filesystem,
+    shred::{ShredOptions, ShreddedFile},
+};
+
+///Evidence that a single file was shredded
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ShredRecord {
+    pub path: PathBuf,
+    pub inode: u64,
+    pub size: u64,
+    ///Number of passes written over the contents. 0 for anything without contents, like directories
+    pub passes: usize,
+    pub pattern: String,
+    ///Whether the overwrite was read back and checked. None if verification wasn't requested
+    pub verified: Option<bool>,
+    pub removed: bool,
+    pub started: DateTime<Utc>,
+    pub finished: DateTime<Utc>,
+    pub fs_type: String,
+    pub host: String,
+}
+
+///A certificate of destruction listing every file a shred run destroyed
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ShredReport {
+    pub host: String,
+    pub created: DateTime<Utc>,
+    pub records: Vec<ShredRecord>,
+    ///SHA-256 of the records, so that changes to them after the fact can be detected
+    pub summary_hash: Option<String>,
+}
+
+impl ShredReport {
+    ///Hashes the records of the report as they are serialised
+    pub fn hash_records(&self) -> io::Result<String> {
+        let json = serde_json::to_vec(&self.records)?;
+        Ok(Sha256::digest(&json)
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect())
+    }
+
+    pub fn read(path: &Path) -> io::Result<ShredReport> {
+        let contents = fs::read(path)?;
+        Ok(serde_json::from_slice(&contents)?)
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_vec_pretty(self)?;
+        fs::write(path, json)
+    }
+}
+
+///Collects records while files are being shredded, possibly from several threads at once
+pub struct ReportBuilder {
+    host: String,
+    created: DateTime<Utc>,
+    records: Mutex<Vec<ShredRecord>>,
+    //Looking up the filesystem means reading the mount table, so it's only done once per device
+    fs_types: Mutex<HashMap<u64, String>>,
+}
+
+impl Default for ReportBuilder {
+    fn default() -> Self {
+        ReportBuilder {
+            host: hostname(),
+            created: Utc::now(),
+            records: Mutex::new(Vec::new()),
+            fs_types: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl ReportBuilder {
+    ///Carries on a report written by an interrupted run, so that the records from before the interruption are
+    /// kept when it's written again
+    pub fn resuming(report: ShredReport) -> ReportBuilder {
+        ReportBuilder {
+            host: report.host,
+            created: report.created,
+            records: Mutex::new(report.records),
+            fs_types: Mutex::new(HashMap::new()),
+        }
+    }
+
+    ///Records a file which has just been shredded. The path is made absolute against the current directory
+    pub fn record(
+        &self,
+        path: &Path,
+        shredded: &ShreddedFile,
+        options: &ShredOptions,
+        started: DateTime<Utc>,
+    ) {
+        let verified = (shredded.overwritten && options.ow_options.verify).then_some(true);
+        let record = ShredRecord {
+            path: std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()),
+            inode: shredded.inode,
+            size: shredded.size,
+            passes: match shredded.overwritten {
+                true => options.ow_options.runs,
+                false => 0,
+            },
+            pattern: options.ow_options.pattern.name().to_string(),
+            verified,
+            removed: shredded.removed,
+            started,
+            finished: Utc::now(),
+            fs_type: self.fs_type(path, shredded.device),
+            host: self.host.clone(),
+        };
+
+        self.records.lock().unwrap().push(record);
+    }
+
+    //The file itself is usually gone by now, but its parent is on the same filesystem
+    fn fs_type(&self, path: &Path, device: u64) -> String {
+        let mut fs_types = self.fs_types.lock().unwrap();
+        fs_types
+            .entry(device)
+            .or_insert_with(|| {
+                let parent = match path.parent() {
+                    Some(p) if !p.as_os_str().is_empty() => p,
+                    _ => Path::new("."),
+                };
+                filesystem::filesystem_info(parent)
+                    .map(|i| i.fs_type)
+                    .unwrap_or_else(|_| "unknown".to_string())
+            })
+            .clone()
+    }
+
+    ///Builds the finished report, hashing it if asked to
+    pub fn finish(self, hash: bool) -> io::Result<ShredReport> {
+        let mut report = ShredReport {
+            host: self.host,
+            created: self.created,
+            records: self.records.into_inner().unwrap(),
+            summary_hash: None,
+        };
+        if hash {
+            report.summary_hash = Some(report.hash_records()?);
+        }
+        Ok(report)
+    }
+}
+
+///The result of checking a report against the filesystem
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReportVerification {
+    ///Paths which were recorded as removed but exist again, or still exist
+    pub remaining: Vec<PathBuf>,
+    ///Whether the summary hash matches the records. None if the report wasn't hashed
+    pub hash_matches: Option<bool>,
+}
+
+impl ReportVerification {
+    pub fn passed(&self) -> bool {
+        self.remaining.is_empty() && self.hash_matches != Some(false)
+    }
+}
+
+///Checks that every path a report says was removed no longer exists, and that the summary hash still matches
+pub fn verify_report(report: &ShredReport) -> io::Result<ReportVerification> {
+    let remaining = report
+        .records
+        .iter()
+        .filter(|r| r.removed && fs::symlink_metadata(&r.path).is_ok())
+        .map(|r| r.path.clone())
+        .collect();
+
+    let hash_matches = match &report.summary_hash {
+        Some(hash) => Some(*hash == report.hash_records()?),
+        None => None,
+    };
+
+    Ok(ReportVerification {
+        remaining,
+        hash_matches,
+    })
+}
+
+///Name of the machine the files were shredded on
+#[cfg(target_os = "linux")]
+pub fn hostname() -> String {
+    let mut buf = [0u8; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } < 0 {
+        return "unknown".to_string();
+    }
+    let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..len]).to_string()
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn hostname() -> String {
+    std::env::var("COMPUTERNAME")
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::files::test_util::{generate_random_filename, temp_file};
+    use crate::shred;
+
+    #[test]
+    fn test_report_round_trip() {
+        let filename = temp_file(b"secret");
+        let options = ShredOptions::default();
+
+        let builder = ReportBuilder::default();
+        let started = Utc::now();
+        let shredded = shred::shred_path(Path::new(&filename), &options, &mut ()).unwrap();
+        builder.record(Path::new(&filename), &shredded, &options, started);
+        let report = builder.finish(true).unwrap();
+
+        let report_name = generate_random_filename();
+        report.write(Path::new(&report_name)).unwrap();
+        let read = ShredReport::read(Path::new(&report_name));
+        fs::remove_file(&report_name).unwrap();
+
+        let read = read.unwrap();
+        assert_eq!(read, report);
+        assert_eq!(read.records.len(), 1);
+        assert_eq!(read.records[0].size, 6);
+        assert_eq!(read.records[0].passes, 1);
+        assert!(read.records[0].path.is_absolute());
+        assert!(verify_report(&read).unwrap().passed());
+    }
+
+    #[test]
+    fn test_verify_report_failures() {
+        let filename = temp_file(b"still here");
+        let options = ShredOptions::default();
+
+        //Record the file as shredded without actually shredding it
+        let builder = ReportBuilder::default();
+        let shredded = ShreddedFile {
+            removed: true,
+            ..Default::default()
+        };
+        builder.record(Path::new(&filename), &shredded, &options, Utc::now());
+        let mut report = builder.finish(true).unwrap();
+
+        let remaining = verify_report(&report).unwrap();
+        fs::remove_file(&filename).unwrap();
+        report.records[0].size = 1000;
+        let tampered = verify_report(&report).unwrap();
+
+        assert_eq!(remaining.remaining.len(), 1);
+        assert!(!remaining.passed());
+        assert!(tampered.remaining.is_empty());
+        assert_eq!(tampered.hash_matches, Some(false));
+    }
+
+    #[test]
+    fn test_report_resumed() {
+        let options = ShredOptions::default();
+        let report_name = generate_random_filename();
+        let shred_recorded = |builder: &ReportBuilder| {
+            let filename = temp_file(b"secret");
+            let started = Utc::now();
+            let shredded = shred::shred_path(Path::new(&filename), &options, &mut ()).unwrap();
+            builder.record(Path::new(&filename), &shredded, &options, started);
+        };
+
+        //The interrupted run writes what it got through, and resuming carries on from that
+        let builder = ReportBuilder::default();
+        shred_recorded(&builder);
+        let interrupted = builder.finish(true).unwrap();
+        interrupted.write(Path::new(&report_name)).unwrap();
+
+        let builder = ReportBuilder::resuming(ShredReport::read(Path::new(&report_name)).unwrap());
+        shred_recorded(&builder);
+        builder
+            .finish(true)
+            .unwrap()
+            .write(Path::new(&report_name))
+            .unwrap();
+        let read = ShredReport::read(Path::new(&report_name));
+        fs::remove_file(&report_name).unwrap();
+
+        let read = read.unwrap();
+        assert_eq!(read.records.len(), 2);
+        assert_eq!(read.records[0], interrupted.records[0]);
+        assert_eq!(read.created, interrupted.created);
+        assert!(verify_report(&read).unwrap().passed());
+    }
+}
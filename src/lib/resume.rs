@@ -0,0 +1,104 @@
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+///A file which was part way through being overwritten when the operation was interrupted
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct InterruptedFile {
+    pub path: PathBuf,
+    ///The pass that was in progress. Resuming starts the overwrite again from this pass
+    pub pass: usize,
+}
+
+///Everything needed to pick an interrupted shred or delete back up where it stopped
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ResumeState {
+    ///The command line the operation was originally started with
+    pub args: Vec<String>,
+    ///Directory the operation was started from, which any relative paths in the arguments are relative to
+    pub cwd: PathBuf,
+    pub recurse: bool,
+    ///Absolute paths which were fully processed before the interruption
+    pub completed: Vec<PathBuf>,
+    ///Absolute paths given to the operation which still exist, and have to be walked again
+    pub pending: Vec<PathBuf>,
+    pub interrupted: Vec<InterruptedFile>,
+    pub time: DateTime<Utc>,
+}
+
+impl ResumeState {
+    pub fn read(path: &Path) -> io::Result<ResumeState> {
+        let contents = fs::read(path)?;
+        Ok(serde_json::from_slice(&contents)?)
+    }
+
+    ///Writes the state, creating its directory if needed. The state is written next to its final location and
+    /// renamed over it so that a crash can't leave a half written state behind
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(tmp, path)
+    }
+
+    ///Returns the pass to start overwriting a file from, which is 1 unless it was interrupted part way
+    pub fn first_pass(&self, path: &Path) -> usize {
+        self.interrupted
+            .iter()
+            .find(|i| i.path == path)
+            .map_or(1, |i| i.pass)
+    }
+}
+
+///Where the resume state is kept when no other location is given. This is rrc/resume.json in the user's state
+/// directory, or None if there isn't one
+pub fn default_state_path() -> Option<PathBuf> {
+    let state_dir = match env::var_os("XDG_STATE_HOME").filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None if cfg!(windows) => PathBuf::from(env::var_os("LOCALAPPDATA")?),
+        None => PathBuf::from(env::var_os("HOME")?)
+            .join(".local")
+            .join("state"),
+    };
+    Some(state_dir.join("rrc").join("resume.json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::files::test_util::generate_random_filename;
+
+    #[test]
+    fn test_resume_state_round_trip() {
+        let dirname = generate_random_filename();
+        let path = Path::new(&dirname).join("resume.json");
+        let state = ResumeState {
+            args: vec!["rrc".to_string(), "shred".to_string(), "a".to_string()],
+            cwd: env::current_dir().unwrap(),
+            recurse: true,
+            completed: vec![PathBuf::from("/tmp/done")],
+            pending: vec![PathBuf::from("/tmp/a")],
+            interrupted: vec![InterruptedFile {
+                path: PathBuf::from("/tmp/a/big"),
+                pass: 2,
+            }],
+            time: Utc::now(),
+        };
+
+        let written = state.write(&path);
+        let read = ResumeState::read(&path);
+        fs::remove_dir_all(&dirname).unwrap();
+
+        written.unwrap();
+        let read = read.unwrap();
+        assert_eq!(read, state);
+        assert_eq!(read.first_pass(Path::new("/tmp/a/big")), 2);
+        assert_eq!(read.first_pass(Path::new("/tmp/done")), 1);
+    }
+}
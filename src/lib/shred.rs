@@ -0,0 +1,705 @@
+use std::{
+    fs::{self, File, FileTimes, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+#[cfg(target_os = "linux")]
+use std::ffi::CString;
+
+use rand::distributions::{Alphanumeric, DistString};
+use trash::TrashItem;
+
+#[cfg(target_os = "linux")]
+use crate::walker::{self, EntryAt};
+use crate::{
+    files::{self, OverwriteObserver, OverwriteOptions},
+    FileErr, RecursiveCallback,
+};
+
+///How a shredded file (or directory) gets removed once its contents have been overwritten.
+/// Mirrors the --remove option of GNU shred
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum RemoveMode {
+    ///Just unlink the file
+    #[default]
+    Unlink,
+    ///Truncate the file, scrub its metadata and rename it to progressively shorter names before unlinking
+    Wipe,
+    ///Same as Wipe, but sync the file and its directory after each step
+    WipeSync,
+}
+
+///Everything controlling how files get shredded
+#[derive(Clone, Debug, Default)]
+pub struct ShredOptions {
+    pub ow_options: OverwriteOptions,
+    pub remove_mode: RemoveMode,
+    ///Shred files which have other hard links. Those links are left pointing at the overwritten data
+    pub force_links: bool,
+    ///Overwrite block and character devices instead of refusing them. The device nodes themselves are kept
+    pub allow_devices: bool,
+    ///Punch holes over regular files once they've been overwritten, letting the filesystem discard their blocks
+    pub discard: bool,
+}
+
+///What was discovered about a file while shredding it
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ShreddedFile {
+    ///Number of hard links the file had, including the one that was removed
+    pub links: u64,
+    ///Whether the file had holes in it. Only its allocated extents were overwritten
+    pub sparse: bool,
+    ///Whether the file's blocks were deallocated after overwriting it. None if that wasn't attempted
+    pub discarded: Option<bool>,
+    ///Inode and device numbers of the file. Always 0 on platforms without them
+    pub inode: u64,
+    pub device: u64,
+    ///Size of the file before it was shredded
+    pub size: u64,
+    ///Whether the contents were overwritten. Directories, symlinks, FIFOs and sockets have none
+    pub overwritten: bool,
+    ///Whether the path was removed. Device nodes are kept
+    pub removed: bool,
+}
+
+///The kinds of file shred_path has to treat differently
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FileKind {
+    Regular,
+    Directory,
+    Symlink,
+    ///FIFOs and sockets, which have no contents of their own to overwrite
+    Special,
+    Device,
+}
+
+impl FileKind {
+    fn of(file_type: fs::FileType) -> FileKind {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::FileTypeExt;
+            if file_type.is_fifo() || file_type.is_socket() {
+                return FileKind::Special;
+            }
+            if file_type.is_block_device() || file_type.is_char_device() {
+                return FileKind::Device;
+            }
+        }
+
+        if file_type.is_symlink() {
+            FileKind::Symlink
+        } else if file_type.is_dir() {
+            FileKind::Directory
+        } else {
+            FileKind::Regular
+        }
+    }
+}
+
+///Overwrites a file and then removes it, reporting progress to the observer.
+/// Directories are only removed, so they must already be empty. Symlinks, FIFOs and sockets are removed
+/// without being opened, and devices are refused unless allow_devices is set
+pub fn shred_path(
+    path: &Path,
+    options: &ShredOptions,
+    observer: &mut dyn OverwriteObserver,
+) -> io::Result<ShreddedFile> {
+    shred_with(
+        fs::symlink_metadata(path)?,
+        || files::open_for_overwrite(path, &options.ow_options),
+        || remove_shredded(path, options.remove_mode),
+        options,
+        observer,
+    )
+}
+
+///Same as shred_path, but acting on a file found by the walker relative to the directory it was found in, so
+/// that nothing is looked up by its path again and trees deeper than PATH_MAX can be shredded
+#[cfg(target_os = "linux")]
+pub fn shred_entry(
+    entry: &EntryAt,
+    options: &ShredOptions,
+    observer: &mut dyn OverwriteObserver,
+) -> io::Result<ShreddedFile> {
+    shred_with(
+        entry.symlink_metadata()?,
+        || entry.open_for_overwrite(&options.ow_options),
+        || remove_shredded_at(entry, options.remove_mode),
+        options,
+        observer,
+    )
+}
+
+//Shreds a file with the given metadata, opening and removing it with the functions given, so that it can be
+//reached either by path or relative to a directory
+fn shred_with(
+    metadata: fs::Metadata,
+    open: impl FnOnce() -> io::Result<File>,
+    remove: impl FnOnce() -> io::Result<()>,
+    options: &ShredOptions,
+    observer: &mut dyn OverwriteObserver,
+) -> io::Result<ShreddedFile> {
+    let mut shredded = ShreddedFile {
+        links: 1,
+        size: metadata.len(),
+        ..Default::default()
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        shredded.inode = metadata.ino();
+        shredded.device = metadata.dev();
+    }
+
+    let kind = FileKind::of(metadata.file_type());
+    if kind == FileKind::Device && !options.allow_devices {
+        return Err(io::Error::other(
+            "refusing to overwrite a block or character device",
+        ));
+    }
+
+    if kind == FileKind::Regular || kind == FileKind::Device {
+        let file = open()?;
+        let metadata = file.metadata()?;
+
+        //The path could have been swapped for something else since we looked at it
+        if FileKind::of(metadata.file_type()) != kind {
+            return Err(io::Error::other(
+                "file changed type while it was being shredded",
+            ));
+        }
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            shredded.links = metadata.nlink();
+            shredded.sparse = metadata.blocks() * 512 < metadata.len();
+        }
+
+        if shredded.links > 1 && !options.force_links {
+            return Err(io::Error::other(format!(
+                "refusing to shred a file with {} other hard link(s), they would be left pointing at the overwritten data",
+                shredded.links - 1
+            )));
+        }
+
+        files::overwrite_file_observed(&file, &options.ow_options, observer)?;
+        shredded.overwritten = true;
+
+        if options.discard && kind == FileKind::Regular {
+            shredded.discarded = Some(files::punch_holes(&file)?);
+        }
+    }
+
+    //Removing a device node wouldn't remove the device, and it's probably still wanted
+    if kind != FileKind::Device {
+        remove()?;
+        shredded.removed = true;
+    }
+    Ok(shredded)
+}
+
+///RecursiveCallback which shreds everything it's run on, for use where there's no output to display
+pub struct Shredder {
+    pub options: ShredOptions,
+}
+
+impl RecursiveCallback for Shredder {
+    fn cb(&mut self, path: &Path) -> Result<bool, FileErr> {
+        shred_path(path, &self.options, &mut ()).map_err(|e| FileErr::map(e, path))?;
+        Ok(true)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn cb_at(&mut self, entry: &EntryAt) -> Result<bool, FileErr> {
+        shred_entry(entry, &self.options, &mut ()).map_err(|e| FileErr::map(e, entry.path()))?;
+        Ok(true)
+    }
+
+    fn display_cb(&mut self, _path: &Path, _is_dir: bool) -> bool {
+        true
+    }
+}
+
+///Shreds the file backing an item in the trash, then removes the item from the trash.
+/// Returns the number of files and directories which were shredded
+pub fn shred_trash_item(item: &TrashItem, options: &ShredOptions) -> Result<usize, FileErr> {
+    let backing_path = files::trash_item_backing_path(item).ok_or_else(|| {
+        FileErr::map(
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                "shredding trash items is not supported on this platform",
+            ),
+            &item.name,
+        )
+    })?;
+
+    let mut shredder = Shredder {
+        options: options.clone(),
+    };
+    let count = match fs::symlink_metadata(&backing_path) {
+        //A file can be in the trash without a backing file if something else removed it, in which case
+        //only the info file is left to clean up
+        Err(e) if e.kind() == io::ErrorKind::NotFound => 0,
+        Err(e) => return Err(FileErr::map(e, &backing_path)),
+        Ok(m) if m.is_dir() => {
+            let options = files::WalkOptions {
+                recurse: true,
+                ..Default::default()
+            };
+            crate::recurse_on_paths(&mut shredder, vec![&backing_path], &options)?
+        }
+        Ok(_) => {
+            shredder.cb(&backing_path)?;
+            1
+        }
+    };
+
+    fs::remove_file(&item.id).map_err(|e| FileErr::map(e, &item.id))?;
+    Ok(count)
+}
+
+///Removes a shredded file or empty directory, first obfuscating its name and metadata if the mode asks for it
+pub fn remove_shredded(path: &Path, mode: RemoveMode) -> io::Result<()> {
+    if mode == RemoveMode::Unlink {
+        return files::remove_file_or_empty_dir(path);
+    }
+    let sync = mode == RemoveMode::WipeSync;
+
+    scrub_metadata(path, sync)?;
+    strip_xattrs(path)?;
+
+    let wiped_path = wipe_name(path, sync)?;
+    files::remove_file_or_empty_dir(&wiped_path)?;
+    if sync {
+        sync_parent_dir(&wiped_path)?;
+    }
+
+    Ok(())
+}
+
+//Same as remove_shredded, but relative to the directory the entry was found in. The paths given to a walk are
+//only known by their whole path, so those are still renamed by path
+#[cfg(target_os = "linux")]
+fn remove_shredded_at(entry: &EntryAt, mode: RemoveMode) -> io::Result<()> {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    if mode == RemoveMode::Unlink {
+        return entry.remove();
+    }
+    if entry.parent == libc::AT_FDCWD {
+        return remove_shredded(entry.path(), mode);
+    }
+    let sync = mode == RemoveMode::WipeSync;
+
+    let kind = FileKind::of(entry.symlink_metadata()?.file_type());
+    scrub_opened(kind, sync, |kind| match kind {
+        FileKind::Directory => entry.open(libc::O_RDONLY | libc::O_DIRECTORY),
+        _ => entry.open(libc::O_WRONLY | libc::O_NONBLOCK),
+    })?;
+    //There's no call to get at the attributes of a name relative to a directory, so this goes through the
+    //directory's descriptor in /proc, falling back to the path if that can't be found
+    let mut at = format!("/proc/self/fd/{}/", entry.parent).into_bytes();
+    at.extend_from_slice(entry.name.to_bytes());
+    match strip_xattrs(Path::new(OsStr::from_bytes(&at))) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => strip_xattrs(entry.path())?,
+        res => res?,
+    }
+
+    let wiped_name = wipe_name_at(entry, sync)?;
+    let wiped = EntryAt {
+        name: &wiped_name,
+        ..*entry
+    };
+    wiped.remove()?;
+    if sync {
+        sync_dir_fd(entry.parent)?;
+    }
+
+    Ok(())
+}
+
+///Truncates a file to zero length and resets its access and modification times to the epoch
+fn scrub_metadata(path: &Path, sync: bool) -> io::Result<()> {
+    let kind = FileKind::of(fs::symlink_metadata(path)?.file_type());
+    scrub_opened(kind, sync, |kind| match kind {
+        FileKind::Directory => File::open(path),
+        _ => OpenOptions::new().write(true).open(path),
+    })
+}
+
+//Scrubs a file of the given kind, opening it with the function given. Only regular files and directories are
+//opened, with directories only having their timestamps reset
+fn scrub_opened(
+    kind: FileKind,
+    sync: bool,
+    open: impl FnOnce(FileKind) -> io::Result<File>,
+) -> io::Result<()> {
+    let times = FileTimes::new()
+        .set_accessed(UNIX_EPOCH)
+        .set_modified(UNIX_EPOCH);
+
+    match kind {
+        FileKind::Regular => {}
+        FileKind::Directory => {
+            //Directories can only be opened like this on unix, elsewhere they just keep their timestamps
+            #[cfg(unix)]
+            open(kind)?.set_times(times)?;
+            return Ok(());
+        }
+        //Opening anything else would either follow it or block
+        _ => return Ok(()),
+    }
+
+    let file = open(kind)?;
+    file.set_len(0)?;
+    file.set_times(times)?;
+    if sync {
+        file.sync_all()?;
+    }
+
+    Ok(())
+}
+
+///Removes every extended attribute we're permitted to remove
+#[cfg(target_os = "linux")]
+fn strip_xattrs(path: &Path) -> io::Result<()> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+
+    let len = unsafe { libc::llistxattr(c_path.as_ptr(), std::ptr::null_mut(), 0) };
+    if len < 0 {
+        let err = io::Error::last_os_error();
+        return match err.raw_os_error() {
+            Some(libc::ENOTSUP) => Ok(()),
+            _ => Err(err),
+        };
+    }
+
+    let mut names = vec![0u8; len as usize];
+    let len = unsafe { libc::llistxattr(c_path.as_ptr(), names.as_mut_ptr().cast(), names.len()) };
+    if len < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    names.truncate(len as usize);
+
+    for name in names.split(|b| *b == 0).filter(|n| !n.is_empty()) {
+        let c_name = CString::new(name)?;
+        if unsafe { libc::lremovexattr(c_path.as_ptr(), c_name.as_ptr()) } < 0 {
+            let err = io::Error::last_os_error();
+            //Attributes like security.selinux are managed by the system and can't be removed by us,
+            //which is fine since they don't carry anything about the original file
+            match err.raw_os_error() {
+                Some(libc::EPERM) | Some(libc::EACCES) | Some(libc::ENODATA) => {}
+                _ => return Err(err),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn strip_xattrs(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+///Renames a file to random names of decreasing length, down to a single character. Returns the final path
+fn wipe_name(path: &Path, sync: bool) -> io::Result<PathBuf> {
+    let name_len = match path.file_name() {
+        Some(n) => n.len(),
+        None => return Ok(path.to_path_buf()),
+    };
+
+    let mut current = path.to_path_buf();
+    for len in (1..=name_len).rev() {
+        let candidate =
+            current.with_file_name(Alphanumeric.sample_string(&mut rand::thread_rng(), len));
+        //Never clobber something else in the directory, just skip this length if the name is taken
+        if fs::symlink_metadata(&candidate).is_ok() {
+            continue;
+        }
+
+        fs::rename(&current, &candidate)?;
+        current = candidate;
+        if sync {
+            sync_parent_dir(&current)?;
+        }
+    }
+
+    Ok(current)
+}
+
+//Same as wipe_name, but renaming the entry within the directory it was found in. Returns the final name
+#[cfg(target_os = "linux")]
+fn wipe_name_at(entry: &EntryAt, sync: bool) -> io::Result<CString> {
+    let mut current = entry.name.to_owned();
+    for len in (1..=current.as_bytes().len()).rev() {
+        let candidate = CString::new(Alphanumeric.sample_string(&mut rand::thread_rng(), len))?;
+        //Never clobber something else in the directory, just skip this length if the name is taken
+        if walker::fstatat(entry.parent, &candidate).is_ok() {
+            continue;
+        }
+
+        walker::renameat(entry.parent, &current, &candidate)?;
+        current = candidate;
+        if sync {
+            sync_dir_fd(entry.parent)?;
+        }
+    }
+
+    Ok(current)
+}
+
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    let parent = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+
+    File::open(parent)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn sync_dir_fd(dir: std::os::fd::RawFd) -> io::Result<()> {
+    if unsafe { libc::fsync(dir) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::files::test_util::{generate_random_filename, temp_dir, temp_file};
+
+    #[test]
+    fn test_remove_shredded_wipe_file() {
+        let dirname = temp_dir();
+        let filename = Path::new(&dirname).join("a_fairly_long_file_name.txt");
+
+        let mut file = File::create(&filename).unwrap();
+        file.write_all(b"secret").unwrap();
+        drop(file);
+
+        remove_shredded(&filename, RemoveMode::WipeSync).unwrap();
+
+        let remaining = fs::read_dir(&dirname).unwrap().count();
+        fs::remove_dir(&dirname).unwrap();
+        assert_eq!(remaining, 0);
+    }
+
+    #[test]
+    fn test_remove_shredded_wipe_dir() {
+        let dirname = temp_dir();
+        let inner = Path::new(&dirname).join("inner_directory");
+        fs::create_dir(&inner).unwrap();
+
+        remove_shredded(&inner, RemoveMode::Wipe).unwrap();
+
+        let remaining = fs::read_dir(&dirname).unwrap().count();
+        fs::remove_dir(&dirname).unwrap();
+        assert_eq!(remaining, 0);
+    }
+
+    #[test]
+    fn test_wipe_name_shortens() {
+        let dirname = temp_dir();
+        let filename = Path::new(&dirname).join("original_name");
+        File::create(&filename).unwrap();
+
+        let wiped = wipe_name(&filename, false).unwrap();
+        let wiped_len = wiped.file_name().unwrap().len();
+        let original_exists = filename.exists();
+
+        fs::remove_file(&wiped).unwrap();
+        fs::remove_dir(&dirname).unwrap();
+
+        assert!(!original_exists);
+        assert_eq!(wiped_len, 1);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_shred_trash_item() {
+        let filename = temp_file(b"secret");
+        trash::delete(&filename).unwrap();
+
+        let item = files::select_from_trash(&filename).unwrap().remove(0);
+        let backing_path = files::trash_item_backing_path(&item).unwrap();
+        assert!(backing_path.exists());
+
+        let options = ShredOptions {
+            remove_mode: RemoveMode::Wipe,
+            ..Default::default()
+        };
+        let count = shred_trash_item(&item, &options).unwrap();
+
+        assert_eq!(count, 1);
+        assert!(!backing_path.exists());
+        assert!(!Path::new(&item.id).exists());
+        assert!(files::select_from_trash(&filename).is_none());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_shred_path_hard_links() {
+        let dirname = temp_dir();
+        let original = Path::new(&dirname).join("original");
+        let link = Path::new(&dirname).join("link");
+        fs::write(&original, b"secret").unwrap();
+        fs::hard_link(&original, &link).unwrap();
+
+        let refused = shred_path(&original, &ShredOptions::default(), &mut ());
+        let untouched = fs::read(&link).unwrap();
+
+        let options = ShredOptions {
+            force_links: true,
+            ..Default::default()
+        };
+        let forced = shred_path(&original, &options, &mut ()).unwrap();
+        let overwritten = fs::read(&link).unwrap();
+
+        fs::remove_dir_all(&dirname).unwrap();
+
+        assert!(refused.is_err());
+        assert_eq!(untouched, b"secret");
+        assert_eq!(forced.links, 2);
+        assert_eq!(overwritten, vec![0u8; 6]);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_shred_path_sparse() {
+        let filename = generate_random_filename();
+        let file = File::create(&filename).unwrap();
+        file.set_len(64 * 1024 * 1024).unwrap();
+        let blocks_before = block_count(&file);
+
+        //The handle keeps the inode around after it's unlinked, so what the shred left behind can be looked at
+        let shredded = shred_path(Path::new(&filename), &ShredOptions::default(), &mut ()).unwrap();
+        let blocks_after = block_count(&file);
+
+        assert!(shredded.sparse);
+        assert!(!Path::new(&filename).exists());
+        assert!(blocks_after <= blocks_before);
+    }
+
+    #[test]
+    fn test_shred_path_discard() {
+        let filename = temp_file(vec![0xa5; 1024 * 1024]);
+        let file = File::open(&filename).unwrap();
+        file.sync_all().unwrap();
+        let options = ShredOptions {
+            discard: true,
+            ..Default::default()
+        };
+
+        let shredded = shred_path(Path::new(&filename), &options, &mut ()).unwrap();
+        let blocks_after = block_count(&file);
+
+        assert!(!Path::new(&filename).exists());
+        match shredded.discarded {
+            Some(true) => assert_eq!(blocks_after, 0),
+            //Filesystems without hole punching leave the blocks allocated
+            Some(false) => (),
+            None => panic!("discarding wasn't attempted"),
+        }
+    }
+
+    #[cfg(unix)]
+    fn block_count(file: &File) -> u64 {
+        use std::os::unix::fs::MetadataExt;
+        file.metadata().unwrap().blocks()
+    }
+
+    #[cfg(not(unix))]
+    fn block_count(_file: &File) -> u64 {
+        0
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_shred_path_symlink() {
+        let dirname = temp_dir();
+        let target = Path::new(&dirname).join("target");
+        let link = Path::new(&dirname).join("link");
+        fs::write(&target, b"secret").unwrap();
+        std::os::unix::fs::symlink("target", &link).unwrap();
+
+        let options = ShredOptions {
+            remove_mode: RemoveMode::Wipe,
+            ..Default::default()
+        };
+        shred_path(&link, &options, &mut ()).unwrap();
+        let link_exists = fs::symlink_metadata(&link).is_ok();
+        let target_contents = fs::read(&target).unwrap();
+        let remaining = fs::read_dir(&dirname).unwrap().count();
+
+        fs::remove_dir_all(&dirname).unwrap();
+
+        assert!(!link_exists);
+        assert_eq!(target_contents, b"secret");
+        assert_eq!(remaining, 1);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_shred_path_broken_symlink() {
+        let link = generate_random_filename();
+        std::os::unix::fs::symlink("does_not_exist", &link).unwrap();
+
+        shred_path(Path::new(&link), &ShredOptions::default(), &mut ()).unwrap();
+
+        assert!(fs::symlink_metadata(&link).is_err());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_shred_path_fifo() {
+        use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+        let fifo = generate_random_filename();
+        let c_path = CString::new(Path::new(&fifo).as_os_str().as_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) }, 0);
+
+        //Nothing ever opens the other end, so this would hang if the FIFO was opened
+        shred_path(Path::new(&fifo), &ShredOptions::default(), &mut ()).unwrap();
+
+        assert!(fs::symlink_metadata(&fifo).is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_shred_path_socket() {
+        let socket = generate_random_filename();
+        let listener = std::os::unix::net::UnixListener::bind(&socket).unwrap();
+
+        shred_path(Path::new(&socket), &ShredOptions::default(), &mut ()).unwrap();
+        drop(listener);
+
+        assert!(fs::symlink_metadata(&socket).is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_shred_path_device_refused() {
+        let device = Path::new("/dev/null");
+
+        let result = shred_path(device, &ShredOptions::default(), &mut ());
+
+        assert!(result.is_err());
+        assert!(fs::symlink_metadata(device).is_ok());
+    }
+}
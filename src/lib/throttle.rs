@@ -0,0 +1,156 @@
+use std::{
+    io,
+    str::FromStr,
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+///Token bucket limiting how fast overwrites write. One bucket is shared by everything writing under the same
+/// limit, including from several threads at once
+#[derive(Debug)]
+pub struct TokenBucket {
+    rate: u64,
+    state: Mutex<BucketState>,
+}
+
+#[derive(Debug)]
+struct BucketState {
+    //Goes negative when a write is bigger than what's in the bucket, the writer then sleeps off the debt
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    ///Creates a bucket allowing rate bytes per second, with up to a second's worth of burst
+    pub fn new(rate: u64) -> TokenBucket {
+        TokenBucket {
+            rate: rate.max(1),
+            state: Mutex::new(BucketState {
+                tokens: rate as f64,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn rate(&self) -> u64 {
+        self.rate
+    }
+
+    ///Takes the given number of bytes out of the bucket, sleeping until the rate allows them to be written
+    pub fn acquire(&self, bytes: u64) {
+        let wait = {
+            let mut state = self.state.lock().unwrap();
+            let now = Instant::now();
+            let elapsed = now.duration_since(state.last_refill).as_secs_f64();
+            state.tokens = (state.tokens + elapsed * self.rate as f64).min(self.rate as f64);
+            state.last_refill = now;
+
+            state.tokens -= bytes as f64;
+            match state.tokens < 0.0 {
+                true => Duration::from_secs_f64(-state.tokens / self.rate as f64),
+                false => Duration::ZERO,
+            }
+        };
+
+        //Sleeping outside the lock lets other writers take their share in the meantime
+        if !wait.is_zero() {
+            thread::sleep(wait);
+        }
+    }
+}
+
+///An I/O scheduling class and priority, as taken by ionice(1)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IoPriority {
+    ///Priority 0 is the highest and 7 the lowest
+    Realtime(u8),
+    BestEffort(u8),
+    ///Only gets disk time when nothing else wants it
+    Idle,
+}
+
+impl FromStr for IoPriority {
+    type Err = String;
+
+    ///Parses idle, best-effort[:LEVEL] or realtime[:LEVEL]. The level defaults to 4
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (class, level) = match s.split_once(':') {
+            Some((class, level)) => (class, Some(level)),
+            None => (s, None),
+        };
+        let level = match level {
+            Some(l) => match l.parse::<u8>() {
+                Ok(l) if l <= 7 => l,
+                _ => return Err(format!("'{l}' is not a priority level between 0 and 7")),
+            },
+            None => 4,
+        };
+
+        match class {
+            "realtime" | "rt" => Ok(IoPriority::Realtime(level)),
+            "best-effort" | "be" => Ok(IoPriority::BestEffort(level)),
+            "idle" => Ok(IoPriority::Idle),
+            _ => Err(format!(
+                "unknown I/O class '{class}', expected idle, best-effort or realtime"
+            )),
+        }
+    }
+}
+
+///Sets the I/O scheduling class and priority of the whole process
+#[cfg(target_os = "linux")]
+pub fn set_io_priority(priority: IoPriority) -> io::Result<()> {
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    const IOPRIO_CLASS_SHIFT: u32 = 13;
+
+    let (class, level) = match priority {
+        IoPriority::Realtime(l) => (1, l),
+        IoPriority::BestEffort(l) => (2, l),
+        IoPriority::Idle => (3, 0),
+    };
+    let ioprio = (class << IOPRIO_CLASS_SHIFT) | level as u32;
+
+    //glibc has no wrapper for ioprio_set. A pid of 0 means the calling process
+    if unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ioprio) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_io_priority(_priority: IoPriority) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "setting the I/O priority is only supported on Linux",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        let bucket = TokenBucket::new(1_000_000);
+
+        //The first second's worth is already in the bucket, the next 200KB has to wait for it
+        let start = Instant::now();
+        bucket.acquire(1_000_000);
+        let burst = start.elapsed();
+        bucket.acquire(200_000);
+        let limited = start.elapsed();
+
+        assert!(burst < Duration::from_millis(100));
+        assert!(limited >= Duration::from_millis(190));
+    }
+
+    #[test]
+    fn test_parse_io_priority() {
+        assert_eq!("idle".parse(), Ok(IoPriority::Idle));
+        assert_eq!("best-effort:7".parse(), Ok(IoPriority::BestEffort(7)));
+        assert_eq!("rt".parse(), Ok(IoPriority::Realtime(4)));
+        assert!("best-effort:8".parse::<IoPriority>().is_err());
+        assert!("fast".parse::<IoPriority>().is_err());
+    }
+}
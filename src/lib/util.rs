@@ -1,3 +1,6 @@
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, NaiveDate};
 use fuzzy_search::automata::LevenshteinAutomata;
 use trash::TrashItem;
 
@@ -38,7 +41,7 @@ pub fn handle_collision_string(
             remaining_items: _,
         } => {
             //This is a little dumb, but it lets me reuse existing code
-            while count_occurences(files, &path) > 1 {
+            while count_occurences(files, path) > 1 {
                 remove_first_from_vec(files, path);
             }
 
@@ -74,6 +77,64 @@ pub fn fuzzy_search(choices: Vec<String>, query: String) -> Vec<String> {
     automata.fuzzy_search(&choices)
 }
 
+///Parses a size like 512, 64K, 1.5GiB or 2T into a number of bytes. Suffixes are binary multiples
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (number, suffix) = s.split_at(split);
+
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("'{s}' is not a valid size"))?;
+    let multiplier: u64 = match suffix.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        other => return Err(format!("unknown size suffix '{other}'")),
+    };
+
+    Ok((number * multiplier as f64) as u64)
+}
+
+///Parses a rate like 50M or 50M/s into a number of bytes per second
+pub fn parse_rate(s: &str) -> Result<u64, String> {
+    parse_size(s.trim().trim_end_matches("/s"))
+}
+
+///Parses an age like 30s, 15m, 12h, 7d or 2w into the time that long ago. A date like 2024-01-31, or an RFC 3339
+/// timestamp, is taken as it is, with dates being midnight UTC
+pub fn parse_age(s: &str) -> Result<SystemTime, String> {
+    let s = s.trim();
+    if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap().and_utc().into());
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Ok(time.into());
+    }
+
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, suffix) = s.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("'{s}' is not a valid age or date"))?;
+    let seconds = match suffix.trim() {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        other => return Err(format!("unknown age suffix '{other}'")),
+    };
+
+    SystemTime::now()
+        .checked_sub(Duration::from_secs(number.saturating_mul(seconds)))
+        .ok_or_else(|| format!("'{s}' is too long ago"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -113,4 +174,33 @@ mod tests {
 
         assert_eq!(count_occurences(&vec, &"Hi"), 2);
     }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("64K"), Ok(64 * 1024));
+        assert_eq!(parse_size("1.5GiB"), Ok(3 * (1 << 29)));
+        assert_eq!(parse_size("2 tb"), Ok(2 << 40));
+        assert!(parse_size("ten").is_err());
+        assert!(parse_size("10X").is_err());
+        assert_eq!(parse_rate("50M/s"), Ok(50 << 20));
+    }
+
+    #[test]
+    fn test_parse_age() {
+        let week = Duration::from_secs(7 * 24 * 60 * 60);
+        let before = SystemTime::now() - week;
+        let parsed = parse_age("7d").unwrap();
+        assert!(parsed >= before && parsed <= SystemTime::now() - week);
+        assert_eq!(
+            parse_age("1970-01-02"),
+            Ok(SystemTime::UNIX_EPOCH + Duration::from_secs(24 * 60 * 60))
+        );
+        assert_eq!(
+            parse_age("1970-01-01T00:01:00Z"),
+            Ok(SystemTime::UNIX_EPOCH + Duration::from_secs(60))
+        );
+        assert!(parse_age("7").is_err());
+        assert!(parse_age("week").is_err());
+    }
 }
@@ -0,0 +1,689 @@
+use std::{
+    ffi::{CStr, CString, OsStr},
+    fs::{self, File},
+    io, mem,
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+        unix::ffi::OsStrExt,
+    },
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    files::{self, OverwriteOptions, WalkOptions},
+    filter::EntryType,
+    protect::{self, ProtectedPath},
+    FileErr, RecursiveCallback,
+};
+
+///A file found by the walker. Besides its path, it knows the directory it was found in, so it can be acted on
+/// relative to that directory instead of by resolving the path again
+pub struct EntryAt<'a> {
+    pub(crate) path: &'a Path,
+    pub(crate) parent: RawFd,
+    pub(crate) name: &'a CStr,
+    pub(crate) kind: Kind,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Kind {
+    Dir,
+    //A symlink to a directory which was followed
+    LinkedDir,
+    //Anything else, including files whose type isn't known
+    Other,
+}
+
+impl EntryAt<'_> {
+    pub fn path(&self) -> &Path {
+        self.path
+    }
+
+    ///Whether the walker descended into the entry. This includes symlinks to directories which were followed
+    pub fn is_dir(&self) -> bool {
+        self.kind != Kind::Other
+    }
+
+    ///Removes the file, or the directory if it's empty. Symlinks are removed themselves, never their targets
+    pub fn remove(&self) -> io::Result<()> {
+        let flags = match self.kind {
+            Kind::Dir => libc::AT_REMOVEDIR,
+            Kind::LinkedDir | Kind::Other => 0,
+        };
+        match unlinkat(self.parent, self.name, flags) {
+            //Paths which weren't walked, like the ones given to a walk which doesn't recurse, can still be directories
+            Err(e) if self.kind == Kind::Other && e.raw_os_error() == Some(libc::EISDIR) => {
+                unlinkat(self.parent, self.name, libc::AT_REMOVEDIR)
+            }
+            res => res,
+        }
+    }
+
+    ///Reads the metadata of the entry itself, without following it if it's a symlink
+    pub fn symlink_metadata(&self) -> io::Result<fs::Metadata> {
+        self.open(libc::O_PATH)?.metadata()
+    }
+
+    ///Opens the file for writing in the mode required by the given OverwriteOptions, like
+    /// files::open_for_overwrite. Symlinks are never followed, and opening a FIFO won't block waiting for a reader
+    pub fn open_for_overwrite(&self, opts: &OverwriteOptions) -> io::Result<File> {
+        let mut flags = libc::O_RDWR | libc::O_NONBLOCK;
+        if opts.direct_io {
+            flags |= libc::O_DIRECT;
+        }
+        self.open(flags)
+    }
+
+    //Opens the entry relative to the directory it was found in, never following it if it's a symlink
+    pub(crate) fn open(&self, flags: libc::c_int) -> io::Result<File> {
+        let fd = openat(
+            self.parent,
+            self.name,
+            flags | libc::O_NOFOLLOW | libc::O_CLOEXEC,
+        )?;
+        Ok(File::from(fd))
+    }
+}
+
+//What the walkers keep track of while walking down from one of the paths they were given
+#[derive(Clone)]
+pub(crate) struct WalkState {
+    //Device and inode of the directories being walked. Only kept when links are followed below the top level,
+    //since following a link back to one of them would otherwise walk the same tree forever
+    ancestors: Vec<(u64, u64)>,
+    //Device of the top level path. Only kept when the walk has to stay on one filesystem
+    device: Option<u64>,
+    //Depth of the entries in the directory being walked, where the top level path is at 0
+    pub(crate) depth: usize,
+}
+
+impl WalkState {
+    pub(crate) fn enter(&mut self, dir: &OwnedFd, options: &WalkOptions) -> io::Result<()> {
+        if options.symlinks.follows(false) {
+            let stat = fstat(dir)?;
+            self.ancestors.push((stat.st_dev, stat.st_ino));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn leave(&mut self) {
+        self.ancestors.pop();
+        self.depth -= 1;
+    }
+}
+
+//What the walkers do with an entry found in a directory they're reading
+pub(crate) enum Found {
+    //A directory to walk, which the callbacks are run on once everything inside it has been
+    Dir(OwnedFd, Kind),
+    //Anything else, which the callbacks are run on straight away
+    Other,
+    //A directory which is already being walked, reached again through a followed link
+    Visited,
+    //A directory on another filesystem, which a walk staying on one filesystem doesn't go into
+    Mount,
+    Protected(ProtectedPath),
+    //Left out by the filters without being looked at
+    Pruned,
+    Failed(io::Error),
+}
+
+#[derive(PartialEq)]
+enum Walked {
+    Complete,
+    //Something inside the directory was skipped, so the directory itself can't be processed either
+    Partial,
+    //The callbacks stopped the traversal
+    Stopped,
+}
+
+///Runs the callbacks of an operation on a set of paths, descending into directories if the options say to.
+/// Every file is opened, checked and removed relative to the directory it's in, with symlinks never followed
+/// unless the options ask for it. This means that nothing in the tree can be swapped for a symlink to send the
+/// walk somewhere else, and that trees deeper than PATH_MAX can be walked. One file descriptor is held open for
+/// each level of the tree being walked
+pub fn walk_paths<T: RecursiveCallback>(
+    op: &mut T,
+    paths: Vec<&Path>,
+    options: &WalkOptions,
+) -> Result<usize, FileErr> {
+    let mut count = 0;
+
+    for path in files::unprotected_paths(paths, options, |e| op.failed(e))? {
+        if options.filter.prunes(path, 0) {
+            continue;
+        }
+        let name = match CString::new(path.as_os_str().as_bytes()) {
+            Ok(name) => name,
+            Err(e) => {
+                op.failed(FileErr::map(e.into(), path))?;
+                continue;
+            }
+        };
+        let mut entry = EntryAt {
+            path,
+            parent: libc::AT_FDCWD,
+            name: &name,
+            kind: Kind::Other,
+        };
+
+        match open_top_level(&name, options) {
+            Ok(Some((dir, kind, mut state))) => {
+                match walk_dir(op, dir, path, &mut count, options, &mut state)? {
+                    Walked::Complete => entry.kind = kind,
+                    Walked::Partial => continue,
+                    Walked::Stopped => break,
+                }
+            }
+            Ok(None) => {}
+            Err(e) => {
+                op.failed(FileErr::map(e, path))?;
+                continue;
+            }
+        }
+
+        if !files::selects_top_level(path, options) {
+            continue;
+        }
+        match op.execute_callbacks_at(&entry) {
+            Ok(true) => count += 1,
+            Ok(false) => {
+                count += 1;
+                break;
+            }
+            Err(e) => op.failed(e)?,
+        }
+    }
+
+    Ok(count)
+}
+
+//Opens one of the paths given to a walk if it's a directory which should be descended into
+pub(crate) fn open_top_level(
+    name: &CStr,
+    options: &WalkOptions,
+) -> io::Result<Option<(OwnedFd, Kind, WalkState)>> {
+    if !options.recurse {
+        return Ok(None);
+    }
+    let Some((dir, kind)) = open_child_dir(libc::AT_FDCWD, name, options.symlinks.follows(true))?
+    else {
+        return Ok(None);
+    };
+    let device = match options.one_file_system {
+        true => Some(fstat(&dir)?.st_dev),
+        false => None,
+    };
+    let state = WalkState {
+        ancestors: Vec::new(),
+        device,
+        depth: 0,
+    };
+    Ok(Some((dir, kind, state)))
+}
+
+fn walk_dir<T: RecursiveCallback>(
+    op: &mut T,
+    dir: OwnedFd,
+    dir_path: &Path,
+    count: &mut usize,
+    options: &WalkOptions,
+    state: &mut WalkState,
+) -> Result<Walked, FileErr> {
+    if let Err(e) = state.enter(&dir, options) {
+        return skip_failed(op, FileErr::map(e, dir_path));
+    }
+    //The whole directory is read before anything in it is touched, so that only one descriptor per level of
+    //the tree has to be kept open
+    let walked = match read_dir(&dir) {
+        Ok(entries) => walk_entries(op, &dir, dir_path, entries, count, options, state),
+        Err(e) => skip_failed(op, FileErr::map(e, dir_path)),
+    };
+    state.leave();
+    walked
+}
+
+fn walk_entries<T: RecursiveCallback>(
+    op: &mut T,
+    dir: &OwnedFd,
+    dir_path: &Path,
+    entries: Vec<(CString, u8)>,
+    count: &mut usize,
+    options: &WalkOptions,
+    state: &mut WalkState,
+) -> Result<Walked, FileErr> {
+    let mut walked = Walked::Complete;
+
+    for (name, d_type) in entries {
+        let path = dir_path.join(OsStr::from_bytes(name.as_bytes()));
+        let mut entry = EntryAt {
+            path: &path,
+            parent: dir.as_raw_fd(),
+            name: &name,
+            kind: Kind::Other,
+        };
+
+        let (d_type, found) = match look_at(dir.as_raw_fd(), &path, &name, d_type, options, state) {
+            Ok(d_type) => (
+                d_type,
+                open_found(dir.as_raw_fd(), &path, &name, d_type, options, state),
+            ),
+            Err(found) => (d_type, found),
+        };
+        match found {
+            Found::Dir(child, kind) => match walk_dir(op, child, &path, count, options, state)? {
+                Walked::Complete => entry.kind = kind,
+                Walked::Partial => {
+                    walked = Walked::Partial;
+                    continue;
+                }
+                Walked::Stopped => return Ok(Walked::Stopped),
+            },
+            Found::Other => {}
+            Found::Visited => continue,
+            Found::Mount => {
+                op.mount_skipped(&path);
+                walked = Walked::Partial;
+                continue;
+            }
+            Found::Protected(protected) => {
+                op.protected_skipped(&protected);
+                walked = Walked::Partial;
+                continue;
+            }
+            Found::Pruned => {
+                walked = Walked::Partial;
+                continue;
+            }
+            Found::Failed(e) => {
+                walked = skip_failed(op, FileErr::map(e, &path))?;
+                continue;
+            }
+        }
+
+        if !selects(&entry, d_type, options, state) {
+            walked = Walked::Partial;
+            continue;
+        }
+        match op.execute_callbacks_at(&entry) {
+            Ok(true) => *count += 1,
+            Ok(false) => {
+                *count += 1;
+                return Ok(Walked::Stopped);
+            }
+            Err(e) => walked = skip_failed(op, e)?,
+        }
+    }
+
+    Ok(walked)
+}
+
+//Checks an entry against the protected patterns and the filters before anything else is done with it. Returns
+//its type, which is looked up if the directory entry didn't have it, or what to do with it instead
+pub(crate) fn look_at(
+    dir: RawFd,
+    path: &Path,
+    name: &CStr,
+    d_type: u8,
+    options: &WalkOptions,
+    state: &WalkState,
+) -> Result<u8, Found> {
+    if let Err(protected) = options.protection.check_entry(path) {
+        return Err(Found::Protected(protected));
+    }
+    if options.filter.prunes(path, state.depth) {
+        return Err(Found::Pruned);
+    }
+    match d_type {
+        libc::DT_UNKNOWN => fstatat(dir, name)
+            .map(|s| d_type_of(&s))
+            .map_err(Found::Failed),
+        t => Ok(t),
+    }
+}
+
+//Whether an entry of this type may be a directory for the walk to go into, which open_found has to find out. The
+//parallel walker leaves opening these to whichever worker walks them
+pub(crate) fn may_be_dir(d_type: u8, options: &WalkOptions) -> bool {
+    d_type == libc::DT_DIR || (d_type == libc::DT_LNK && options.symlinks.follows(false))
+}
+
+//Opens an entry if it's a directory which should be walked, and checks that it's one the walk can go into. The
+//type has to be known already. Anything which doesn't turn out to be a directory once it's opened, such as a
+//directory swapped for a symlink since it was listed, is left to the callbacks like any other file. Marked
+//entries are skipped either way, with directories checked through the descriptor which is then walked
+pub(crate) fn open_found(
+    dir: RawFd,
+    path: &Path,
+    name: &CStr,
+    d_type: u8,
+    options: &WalkOptions,
+    state: &WalkState,
+) -> Found {
+    let child = match d_type {
+        libc::DT_DIR => open_child_dir(dir, name, false),
+        libc::DT_LNK if options.symlinks.follows(false) => open_child_dir(dir, name, true),
+        _ => Ok(None),
+    };
+    let (child, kind) = match child {
+        Ok(Some(child)) => child,
+        Ok(None) => {
+            return marked(
+                path,
+                || protect::has_marker_xattr_at(dir, name, path),
+                options,
+            )
+            .unwrap_or(Found::Other)
+        }
+        Err(e) => return Found::Failed(e),
+    };
+
+    if kind == Kind::LinkedDir {
+        if let Err(protected) = options.protection.check_followed(path) {
+            return Found::Protected(protected);
+        }
+    }
+    if !state.ancestors.is_empty() || state.device.is_some() {
+        match fstat(&child) {
+            //Links back to a directory being walked are just links, the directory itself is left alone
+            Ok(s) if state.ancestors.contains(&(s.st_dev, s.st_ino)) => {
+                return match kind {
+                    Kind::Dir => Found::Visited,
+                    Kind::LinkedDir | Kind::Other => marked(
+                        path,
+                        || protect::has_marker_xattr_at(dir, name, path),
+                        options,
+                    )
+                    .unwrap_or(Found::Other),
+                }
+            }
+            Ok(s) if state.device.is_some_and(|d| d != s.st_dev) => return Found::Mount,
+            Ok(_) => {}
+            Err(e) => return Found::Failed(e),
+        }
+    }
+
+    //A followed link can be marked itself, as well as the directory it points at
+    let is_marked = || {
+        protect::is_marked_dir(&child)
+            || (kind == Kind::LinkedDir && protect::has_marker_xattr_at(dir, name, path))
+    };
+    if let Some(found) = marked(path, is_marked, options) {
+        return found;
+    }
+    Found::Dir(child, kind)
+}
+
+fn marked(path: &Path, is_marked: impl FnOnce() -> bool, options: &WalkOptions) -> Option<Found> {
+    options
+        .protection
+        .check_marked_entry(path, is_marked)
+        .err()
+        .map(Found::Protected)
+}
+
+//Whether the filters select an entry found while walking, once everything inside it has been if it was walked
+pub(crate) fn selects(
+    entry: &EntryAt,
+    d_type: u8,
+    options: &WalkOptions,
+    state: &WalkState,
+) -> bool {
+    let entry_type = match (entry.kind, d_type) {
+        (Kind::Dir, _) | (Kind::Other, libc::DT_DIR) => EntryType::Dir,
+        (_, libc::DT_LNK) => EntryType::Symlink,
+        (_, libc::DT_REG) => EntryType::File,
+        _ => EntryType::Other,
+    };
+    options
+        .filter
+        .selects(entry.path, state.depth, entry_type, || {
+            fstatat(entry.parent, entry.name)
+                .ok()
+                .map(|s| stat_times(&s))
+        })
+}
+
+//Passes an error to the operation, which either stops the walk with it or has the failed entry skipped
+fn skip_failed<T: RecursiveCallback>(op: &mut T, err: FileErr) -> Result<Walked, FileErr> {
+    op.failed(err)?;
+    Ok(Walked::Partial)
+}
+
+//Opens a directory to walk it. Returns None if the path isn't a directory, or is a symlink which isn't to be
+//followed
+fn open_child_dir(parent: RawFd, name: &CStr, follow: bool) -> io::Result<Option<(OwnedFd, Kind)>> {
+    let flags = libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC;
+    let not_dir = |e: &io::Error| matches!(e.raw_os_error(), Some(libc::ENOTDIR | libc::ELOOP));
+
+    match openat(parent, name, flags | libc::O_NOFOLLOW) {
+        Ok(fd) => Ok(Some((fd, Kind::Dir))),
+        //Symlinks fail to open with O_NOFOLLOW, so if opening through them works they're links to directories
+        Err(e) if not_dir(&e) && follow => match openat(parent, name, flags) {
+            Ok(fd) => Ok(Some((fd, Kind::LinkedDir))),
+            Err(e) if not_dir(&e) || e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        },
+        Err(e) if not_dir(&e) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+pub(crate) fn read_dir(dir: &OwnedFd) -> io::Result<Vec<(CString, u8)>> {
+    //closedir closes the descriptor it was given, so the stream gets a duplicate of its own
+    let fd = unsafe { libc::fcntl(dir.as_raw_fd(), libc::F_DUPFD_CLOEXEC, 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let stream = unsafe { libc::fdopendir(fd) };
+    if stream.is_null() {
+        let err = io::Error::last_os_error();
+        unsafe { libc::close(fd) };
+        return Err(err);
+    }
+    //The duplicate shares its offset with the original, which may have been read before
+    unsafe { libc::rewinddir(stream) };
+
+    let mut entries = Vec::new();
+    let res = loop {
+        //readdir only signals errors through errno, so it has to be cleared first
+        unsafe { *libc::__errno_location() = 0 };
+        let entry = unsafe { libc::readdir64(stream) };
+        if entry.is_null() {
+            break match io::Error::last_os_error() {
+                e if e.raw_os_error() == Some(0) => Ok(entries),
+                e => Err(e),
+            };
+        }
+
+        let entry = unsafe { &*entry };
+        let name = unsafe { CStr::from_ptr(entry.d_name.as_ptr()) };
+        if name.to_bytes() != b"." && name.to_bytes() != b".." {
+            entries.push((name.to_owned(), entry.d_type));
+        }
+    };
+    unsafe { libc::closedir(stream) };
+
+    res
+}
+
+pub(crate) fn fstatat(parent: RawFd, name: &CStr) -> io::Result<libc::stat64> {
+    let mut stat: libc::stat64 = unsafe { mem::zeroed() };
+    if unsafe { libc::fstatat64(parent, name.as_ptr(), &mut stat, libc::AT_SYMLINK_NOFOLLOW) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(stat)
+}
+
+//Devices, sockets and pipes have types of their own in directory entries, but the walker doesn't need to tell
+//them apart
+fn d_type_of(stat: &libc::stat64) -> u8 {
+    match stat.st_mode & libc::S_IFMT {
+        libc::S_IFDIR => libc::DT_DIR,
+        libc::S_IFLNK => libc::DT_LNK,
+        libc::S_IFREG => libc::DT_REG,
+        _ => libc::DT_UNKNOWN,
+    }
+}
+
+//The size and modification time of an entry, for the filters
+fn stat_times(stat: &libc::stat64) -> (u64, SystemTime) {
+    let modified = match u64::try_from(stat.st_mtime) {
+        Ok(secs) => SystemTime::UNIX_EPOCH + Duration::new(secs, stat.st_mtime_nsec as u32),
+        Err(_) => SystemTime::UNIX_EPOCH - Duration::from_secs(stat.st_mtime.unsigned_abs()),
+    };
+    (stat.st_size as u64, modified)
+}
+
+fn fstat(fd: &OwnedFd) -> io::Result<libc::stat64> {
+    let mut stat: libc::stat64 = unsafe { mem::zeroed() };
+    if unsafe { libc::fstat64(fd.as_raw_fd(), &mut stat) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(stat)
+}
+
+fn openat(parent: RawFd, name: &CStr, flags: libc::c_int) -> io::Result<OwnedFd> {
+    let fd = unsafe { libc::openat(parent, name.as_ptr(), flags) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+fn unlinkat(parent: RawFd, name: &CStr, flags: libc::c_int) -> io::Result<()> {
+    if unsafe { libc::unlinkat(parent, name.as_ptr(), flags) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+pub(crate) fn renameat(dir: RawFd, from: &CStr, to: &CStr) -> io::Result<()> {
+    if unsafe { libc::renameat(dir, from.as_ptr(), dir, to.as_ptr()) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::files::test_util::{generate_random_filename, temp_dir};
+    use crate::shred::{RemoveMode, ShredOptions, Shredder};
+
+    struct Remover;
+
+    impl RecursiveCallback for Remover {
+        fn cb(&mut self, path: &Path) -> Result<bool, FileErr> {
+            panic!("{} was passed by path", path.display());
+        }
+
+        fn cb_at(&mut self, entry: &EntryAt) -> Result<bool, FileErr> {
+            entry.remove().map_err(|e| FileErr::map(e, entry.path()))?;
+            Ok(true)
+        }
+
+        fn display_cb(&mut self, _path: &Path, _is_dir: bool) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_walk_paths_deeper_than_path_max() {
+        let dirname = deeper_than_path_max();
+        let walk = WalkOptions {
+            recurse: true,
+            ..Default::default()
+        };
+        let count = walk_paths(&mut Remover, vec![Path::new(&dirname)], &walk);
+        let exists = Path::new(&dirname).exists();
+
+        assert_eq!(count.unwrap(), 32);
+        assert!(!exists);
+    }
+
+    #[test]
+    fn test_walk_paths_shreds_deeper_than_path_max() {
+        let walk = WalkOptions {
+            recurse: true,
+            ..Default::default()
+        };
+        //Wiping renames everything before it's removed, which has to be done relative to the directory too
+        for remove_mode in [RemoveMode::Unlink, RemoveMode::Wipe] {
+            let dirname = deeper_than_path_max();
+            let mut shredder = Shredder {
+                options: ShredOptions {
+                    remove_mode,
+                    ..Default::default()
+                },
+            };
+            let count = walk_paths(&mut shredder, vec![Path::new(&dirname)], &walk);
+            let exists = Path::new(&dirname).exists();
+
+            assert_eq!(count.unwrap(), 32);
+            assert!(!exists);
+        }
+    }
+
+    //Makes a tree with a file at the bottom whose path is longer than PATH_MAX, returning the top of it
+    fn deeper_than_path_max() -> String {
+        let dirname = temp_dir();
+
+        //Each level is created relative to the one above it, since the full path gets too long to use
+        let level_name = CString::new("d".repeat(200)).unwrap();
+        let mut dir = openat(
+            libc::AT_FDCWD,
+            &CString::new(dirname.clone()).unwrap(),
+            libc::O_RDONLY | libc::O_DIRECTORY,
+        )
+        .unwrap();
+        for _ in 0..30 {
+            assert_eq!(
+                unsafe { libc::mkdirat(dir.as_raw_fd(), level_name.as_ptr(), 0o755) },
+                0
+            );
+            dir = openat(
+                dir.as_raw_fd(),
+                &level_name,
+                libc::O_RDONLY | libc::O_DIRECTORY,
+            )
+            .unwrap();
+        }
+        let file_name = CString::new("file").unwrap();
+        let file = openat(dir.as_raw_fd(), &file_name, libc::O_WRONLY | libc::O_CREAT).unwrap();
+        io::Write::write_all(&mut File::from(file), b"secret").unwrap();
+
+        dirname
+    }
+
+    #[test]
+    fn test_walk_paths_removes_links_not_targets() {
+        let dirname = generate_random_filename();
+        let target = generate_random_filename();
+        fs::create_dir_all(Path::new(&dirname).join("sub")).unwrap();
+        fs::create_dir(&target).unwrap();
+        fs::write(Path::new(&target).join("file"), b"data").unwrap();
+        std::os::unix::fs::symlink(
+            fs::canonicalize(&target).unwrap(),
+            Path::new(&dirname).join("sub").join("link"),
+        )
+        .unwrap();
+
+        let walk = WalkOptions {
+            recurse: true,
+            ..Default::default()
+        };
+        let count = walk_paths(&mut Remover, vec![Path::new(&dirname)], &walk);
+        let exists = Path::new(&dirname).exists();
+        let target_kept = Path::new(&target).join("file").exists();
+        fs::remove_dir_all(&target).unwrap();
+
+        assert_eq!(count.unwrap(), 3);
+        assert!(!exists);
+        assert!(target_kept);
+    }
+}
@@ -1,12 +1,25 @@
-use std::path::PathBuf;
-
-use clap::{arg, command, value_parser, Arg};
+use clap::{arg, builder::FalseyValueParser, command, value_parser, ArgAction, Command};
 use colored::Colorize;
+use rrc_lib::{filter::EntryType, throttle::IoPriority};
 
+mod executor;
 mod operations;
 mod output;
 
 fn main() {
+    let matches = build_cli().get_matches_from(wild::args());
+
+    match operations::run_operation_from_args(matches) {
+        Ok(_) => {}
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+///Builds the command line interface. This is also used to parse the arguments an interrupted operation was run with
+pub fn build_cli() -> Command {
     let help_template = format!(
         "{}{} - {}{}{}{}{}{}{}",
         "{before-help}",
@@ -24,19 +37,110 @@ fn main() {
         .num_args(1..)
         .value_parser(value_parser!(String));
 
-    let matches = command!()
+    let ow_runs_arg = arg!(ow_runs: -n --overwrite_runs <VALUE>)
+        .default_value("1")
+        .value_parser(value_parser!(usize));
+
+    let verify_arg =
+        arg!(verify: --verify "Read files back after overwriting to check the overwrite succeeded");
+
+    let slack_arg = arg!(slack: --slack "Also overwrite the slack space between the end of each file and the end of its last block");
+
+    let remove_arg = arg!(remove: --remove <HOW> "How to remove files after overwriting. wipe and wipesync obfuscate the name and metadata first")
+        .value_parser(["unlink", "wipe", "wipesync"])
+        .default_value("unlink");
+
+    let pattern_arg = arg!(pattern: --pattern <PATTERN> "What to overwrite files with. random uses fresh data for every pass")
+        .value_parser(["zeroes", "ones", "random"])
+        .default_value("zeroes");
+
+    let buffer_size_arg =
+        arg!(buffer_size: --"buffer-size" <SIZE> "Size of each write, e.g. 256K or 4M")
+            .default_value("1M")
+            .value_parser(rrc_lib::util::parse_size);
+
+    let io_uring_arg = arg!(io_uring: --"io-uring" "Submit writes through io_uring (Linux only, requires the io-uring feature)");
+
+    let bwlimit_arg =
+        arg!(bwlimit: --bwlimit <RATE> "Limit how fast data is written, e.g. 50M for 50MiB/s")
+            .value_parser(rrc_lib::util::parse_rate);
+
+    let ionice_arg = arg!(ionice: --ionice <CLASS> "Set the I/O scheduling class and priority, as idle, best-effort[:0-7] or realtime[:0-7] (Linux only)")
+        .value_parser(|s: &str| s.parse::<IoPriority>());
+
+    let follow_symlinks_arg = arg!(follow_symlinks: -L --"follow-symlinks" "Descend into symlinks to directories while recursing. The links themselves are removed, not their targets")
+        .conflicts_with("follow_command_line");
+
+    let follow_command_line_arg = arg!(follow_command_line: -H "Descend into symlinks to directories given on the command line, but not ones found while recursing");
+
+    let one_file_system_arg = arg!(one_file_system: -x --"one-file-system" "Don't descend into directories on other filesystems while recursing. Set RRC_ONE_FILE_SYSTEM=1 to make this the default")
+        .env("RRC_ONE_FILE_SYSTEM")
+        .value_parser(FalseyValueParser::new());
+
+    let no_one_file_system_arg = arg!(no_one_file_system: --"no-one-file-system" "Descend into directories on other filesystems even if RRC_ONE_FILE_SYSTEM is set");
+
+    let keep_going_arg = arg!(keep_going: -k --"keep-going" "Carry on past files which fail, skipping the directories containing them, and list the failures at the end");
+
+    let plan_out_arg = arg!(plan_out: --"plan-out" <FILE> "Write what would be done to a plan for rrc apply instead of doing it")
+        .value_parser(value_parser!(String));
+
+    let no_preserve_root_arg = arg!(no_preserve_root: --"no-preserve-root" "Don't refuse to operate on /, system directories, the home directory, the trash, the working directory or its parents, or protected patterns");
+
+    let allow_protected_arg = arg!(allow_protected: --"allow-protected" <PATH> "Operate on a protected path, and anything protected inside it, anyway. Can be given more than once")
+        .action(ArgAction::Append)
+        .value_parser(value_parser!(String));
+
+    let protect_arg = arg!(protect: --protect <GLOB> "Also refuse paths matching a glob, on top of the ones in rrc/protected in the config directory. Can be given more than once")
+        .action(ArgAction::Append)
+        .value_parser(value_parser!(String));
+
+    //Filters for recursive operations. Directories are only removed once everything inside them has been
+    let filter_args = [
+        arg!(include: --include <GLOB> "Only act on files matching a glob. Globs without a / are matched against file names. Can be given more than once")
+            .action(ArgAction::Append)
+            .value_parser(|s: &str| glob::Pattern::new(s)),
+        arg!(exclude: --exclude <GLOB> "Leave out anything matching a glob, without descending into matching directories. Can be given more than once")
+            .action(ArgAction::Append)
+            .value_parser(|s: &str| glob::Pattern::new(s)),
+        arg!(entry_type: --"type" <TYPE> "Only act on files (f), directories (d) or symlinks (l). Takes a comma separated list")
+            .action(ArgAction::Append)
+            .value_delimiter(',')
+            .value_parser(|s: &str| s.parse::<EntryType>()),
+        arg!(min_size: --"min-size" <SIZE> "Only act on files at least this big, e.g. 10M")
+            .value_parser(rrc_lib::util::parse_size),
+        arg!(max_size: --"max-size" <SIZE> "Only act on files at most this big")
+            .value_parser(rrc_lib::util::parse_size),
+        arg!(older_than: --"older-than" <AGE> "Only act on files last modified longer ago than this, e.g. 30d or 12h, or before a date like 2024-01-31")
+            .value_parser(rrc_lib::util::parse_age),
+        arg!(newer_than: --"newer-than" <AGE> "Only act on files last modified more recently than this")
+            .value_parser(rrc_lib::util::parse_age),
+        arg!(min_depth: --"min-depth" <N> "Leave alone anything less than N levels below the paths given, which are at level 0")
+            .value_parser(value_parser!(usize)),
+        arg!(max_depth: --"max-depth" <N> "Don't descend more than N levels below the paths given")
+            .value_parser(value_parser!(usize)),
+    ];
+
+    let force_links_arg = arg!(force_links: --"force-links" "Shred files even if they have other hard links, which will be left pointing at the overwritten data");
+
+    command!()
         .help_template(help_template)
         .subcommand_required(true)
         .subcommand(
             command!("trash")
                 .short_flag('t')
                 .about("Move files to the recycle bin")
+                .arg(no_preserve_root_arg.clone())
+                .arg(allow_protected_arg.clone())
+                .arg(protect_arg.clone())
+                .args(filter_args.clone())
+                .arg(plan_out_arg.clone())
                 .arg(files_arg.clone()),
         )
         .subcommand(
             command!("restore")
                 .short_flag('r')
                 .about("Restore files from the recycle bin")
+                .arg(plan_out_arg.clone())
                 .arg(files_arg.clone()),
         )
         .subcommand(
@@ -44,25 +148,129 @@ fn main() {
                 .short_flag('p')
                 .about("Remove files from the recycle bin")
                 .arg(arg!(all: -a --all))
+                .arg(arg!(shred: --shred "Shred the files in the recycle bin instead of just removing them"))
+                .arg(ow_runs_arg.clone())
+                .arg(verify_arg.clone())
+                .arg(slack_arg.clone())
+                .arg(pattern_arg.clone())
+                .arg(remove_arg.clone())
+                .arg(force_links_arg.clone())
+                .arg(plan_out_arg.clone())
                 .arg(files_arg.clone()),
         )
         .subcommand(
             command!("delete")
                 .short_flag('d')
                 .about("Delete files permanently")
+                .arg(ionice_arg.clone())
+                .arg(
+                    arg!(jobs: -j --jobs <N> "Number of threads to walk directories and delete files with")
+                        .default_value("1")
+                        .value_parser(value_parser!(usize)),
+                )
+                .arg(keep_going_arg.clone())
+                .arg(follow_symlinks_arg.clone())
+                .arg(follow_command_line_arg.clone())
+                .arg(one_file_system_arg.clone())
+                .arg(no_one_file_system_arg.clone())
+                .arg(no_preserve_root_arg.clone())
+                .arg(allow_protected_arg.clone())
+                .arg(protect_arg.clone())
+                .args(filter_args.clone())
+                .arg(plan_out_arg.clone())
                 .arg(files_arg.clone()),
         )
         .subcommand(
             command!("shred")
                 .short_flag('s')
                 .about("Securely delete files by overwriting them first")
+                .arg(ow_runs_arg.clone())
+                .arg(arg!(direct: --direct "Bypass the page cache with O_DIRECT while overwriting (Linux only)"))
+                .arg(verify_arg.clone())
+                .arg(slack_arg.clone())
+                .arg(pattern_arg.clone())
+                .arg(buffer_size_arg.clone())
+                .arg(io_uring_arg.clone())
+                .arg(bwlimit_arg.clone())
+                .arg(ionice_arg.clone())
+                .arg(remove_arg.clone())
+                .arg(force_links_arg.clone())
+                .arg(keep_going_arg.clone())
+                .arg(follow_symlinks_arg.clone())
+                .arg(follow_command_line_arg.clone())
+                .arg(one_file_system_arg.clone())
+                .arg(no_one_file_system_arg.clone())
+                .arg(no_preserve_root_arg.clone())
+                .arg(allow_protected_arg.clone())
+                .arg(protect_arg.clone())
+                .args(filter_args.clone())
+                .arg(arg!(strict: --strict "Refuse to shred files on filesystems where overwriting them may not destroy their contents"))
+                .arg(arg!(discard: --discard "Deallocate each file's blocks after overwriting it so SSDs and thin provisioned storage can discard them"))
+                .arg(arg!(allow_devices: --"allow-devices" "Overwrite block and character devices instead of refusing them. Device nodes are never removed"))
                 .arg(
-                    arg!(ow_runs: -n --overwrite_runs <VALUE>)
+                    arg!(report: --report <FILE> "Write a JSON certificate of destruction listing every shredded file")
+                        .value_parser(value_parser!(String)),
+                )
+                .arg(
+                    arg!(report_hash: --"report-hash" "Add a SHA-256 summary hash of the records to the report")
+                        .requires("report"),
+                )
+                .arg(
+                    arg!(jobs: -j --jobs <N> "Number of files to shred in parallel")
                         .default_value("1")
                         .value_parser(value_parser!(usize)),
                 )
+                .arg(plan_out_arg.clone())
                 .arg(files_arg.clone()),
         )
+        .subcommand(
+            command!("resume")
+                .about("Continue a shred or delete which was interrupted")
+                .arg(
+                    arg!(state: [STATE] "State file to resume from. Defaults to the one saved by the last interrupted operation")
+                        .value_parser(value_parser!(String)),
+                ),
+        )
+        .subcommand(
+            command!("apply")
+                .about("Carry out a plan written with --plan-out, refusing entries which have changed since")
+                .arg(arg!(plan: <PLAN> "Plan file to apply").value_parser(value_parser!(String))),
+        )
+        .subcommand(
+            command!("protect")
+                .about("Mark files and directories so that deleting, shredding or trashing them, or anything containing them, is refused")
+                .arg(arg!(xattr: --xattr "Mark directories with the user.rrc.protect attribute instead of a .rrc-protect file. Other files are always marked with the attribute"))
+                .arg(files_arg.clone()),
+        )
+        .subcommand(
+            command!("unprotect")
+                .about("Remove the protection markers from files and directories")
+                .arg(files_arg.clone()),
+        )
+        .subcommand(
+            command!("verify-report")
+                .about("Check that every file listed in a shred report no longer exists")
+                .arg(arg!(report: <FILE>).value_parser(value_parser!(String))),
+        )
+        .subcommand(
+            command!("wipe-free")
+                .about("Overwrite the free space of a filesystem to destroy the contents of previously deleted files")
+                .arg(
+                    arg!(dir: <DIRECTORY> "A directory on the filesystem to wipe. The fill files are created inside it")
+                        .value_parser(value_parser!(String)),
+                )
+                .arg(
+                    arg!(reserve: --reserve <SIZE> "Amount of free space to leave unwritten, e.g. 512M or 2G")
+                        .default_value("0")
+                        .value_parser(rrc_lib::util::parse_size),
+                )
+                .arg(arg!(direct: --direct "Bypass the page cache with O_DIRECT while writing (Linux only)"))
+                .arg(pattern_arg.clone())
+                .arg(buffer_size_arg.clone())
+                .arg(io_uring_arg.clone())
+                .arg(bwlimit_arg.clone())
+                .arg(ionice_arg.clone()),
+        )
         .subcommand(
             command!("search")
                 .short_flag('S')
@@ -82,7 +290,10 @@ fn main() {
                     arg!(target: <TARGET>)
                         .num_args(1)
                         .value_parser(value_parser!(String)),
-                ),
+                )
+                .arg(no_preserve_root_arg.clone())
+                .arg(allow_protected_arg.clone())
+                .arg(protect_arg.clone()),
         )
         .subcommand(
             command!("list")
@@ -95,10 +306,5 @@ fn main() {
                 .about("List files in the recycle bin"),
         )
         .arg(arg!(recurse: -R --recurse "Run delete and shred on directories without a prompt"))
-        .get_matches_from(wild::args());
-
-    match operations::run_operation_from_args(matches) {
-        Ok(_) => {}
-        Err(e) => eprintln!("{e}"),
-    }
+        .arg(arg!(dry_run: --"dry-run" "List what would be done without changing any files or the trash").global(true))
 }
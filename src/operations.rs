@@ -1,31 +1,51 @@
-use core::num;
 use std::{
-    default,
+    collections::{HashMap, HashSet},
+    env,
     error::Error,
     fmt::Display,
-    fs::{self, File, OpenOptions},
-    io::ErrorKind,
+    fs, io,
     path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex, OnceLock, PoisonError, RwLock,
+    },
 };
 
-use clap::{builder::Str, ArgMatches};
+use clap::{parser::ValueSource, ArgMatches};
+use glob::Pattern;
 
 use fuzzy_search::distance::levenshtein;
+#[cfg(target_os = "linux")]
+use rrc_lib::walker::EntryAt;
 use rrc_lib::{
     files::{
         self, get_existent_paths, get_existent_trash_items, path_to_string,
-        path_vec_from_string_vec, trash_items_from_names, trash_items_to_names,
+        path_vec_from_string_vec, trash_items_from_names, trash_items_to_names, OverwriteOptions,
+        OverwritePattern, SymlinkPolicy, WalkOptions, WriteBackend,
     },
-    util, FileErr, RecursiveCallback,
+    filesystem,
+    filter::{EntryType, Filter},
+    freespace::{self, WipeFreeOptions},
+    plan::{Plan, PlanEntry, Stale},
+    protect::{self, ProtectedKind, ProtectedPath, Protection},
+    report::{self, ReportBuilder, ShredReport},
+    resume::{self, InterruptedFile, ResumeState},
+    shred::{self, RemoveMode, ShredOptions, ShreddedFile},
+    throttle::{self, IoPriority, TokenBucket},
+    util, FileErr, ParallelCallback, RecursiveCallback,
 };
 use trash::{
     os_limited::{self, purge_all},
     TrashItem,
 };
 
-use crate::output::{self, prompt_recursion, OpSpinner, TrashList};
+use crate::{
+    executor::Executor,
+    output::{self, prompt_recursion, OpSpinner, ShredProgress, TrashList},
+};
 
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum OPERATION {
     DELETE,
     TRASH,
@@ -33,6 +53,12 @@ pub enum OPERATION {
     SHRED { num_runs: usize },
     LIST,
     PURGE { all_files: bool },
+    WIPEFREE,
+    VERIFYREPORT,
+    RESUME,
+    APPLY,
+    PROTECT,
+    UNPROTECT,
 }
 
 #[derive(Debug)]
@@ -50,13 +76,10 @@ impl Display for OperationError {
         if self.operation == OPERATION::LIST {
             write!(f, "Error while getting trash list: {}", self.err)
         } else {
-            let file;
-            if self.file.is_some() {
-                file = self.file.clone().unwrap();
-            } else {
-                file = "[no file set]".to_string()
+            match &self.file {
+                Some(file) => write!(f, "Error while {} {}: {}", op_string, file, self.err),
+                None => write!(f, "Error while {}: {}", op_string, self.err),
             }
-            write!(f, "Error while {} {}: {}", op_string, file, self.err)
         }
     }
 }
@@ -73,25 +96,304 @@ impl OperationError {
 
 pub fn run_operation_from_args(args: ArgMatches) -> Result<(), OperationError> {
     let recurse_default = args.get_flag("recurse");
-    return match args.subcommand() {
-        Some(("trash", m)) => TrashOperation::trash(get_files_from_sub(m)),
-        Some(("restore", m)) => RestoreOperation::operate(get_files_from_sub(m)),
-        Some(("delete", m)) => {
-            DeleteOperation::default().operate(get_files_from_sub(m), recurse_default)
-        }
-        Some(("purge", m)) => BasicOperations::purge(get_files_from_sub(m), m.get_flag("all")),
-        Some(("shred", m)) => ShredOperation::new(*m.get_one("ow_runs").unwrap())
-            .operate(get_files_from_sub(m), recurse_default),
-        Some(("search", m)) => SearchOperation::new(
-            m.get_one("command").unwrap(),
-            m.get_one("target").unwrap(),
-            m.get_one("dir").unwrap(),
-        )
-        .operate(),
+    let dry_run = args.get_flag("dry_run");
+    if let Some((_, m)) = args.subcommand() {
+        apply_io_priority(m);
+    }
+
+    match args.subcommand() {
+        Some(("trash", m)) => TrashOperation::trash(
+            get_files_from_sub(m),
+            WalkOptions {
+                recurse: true,
+                protection: protection_from_args(m, OPERATION::TRASH)?,
+                filter: filter_from_args(m),
+                ..Default::default()
+            },
+            Executor::from_args(m),
+        ),
+        Some(("restore", m)) => {
+            RestoreOperation::operate(get_files_from_sub(m), Executor::from_args(m))
+        }
+        Some(("delete", m)) => delete_operation_from_args(m).operate(
+            get_files_from_sub(m),
+            recurse_default,
+            walk_options_from_args(m, OPERATION::DELETE)?,
+        ),
+        Some(("purge", m)) => BasicOperations::purge(
+            get_files_from_sub(m),
+            m.get_flag("all"),
+            purge_shred_options_from_args(m),
+            Executor::from_args(m),
+        ),
+        Some(("shred", m)) => shred_operation_from_args(m).operate(
+            get_files_from_sub(m),
+            recurse_default,
+            walk_options_from_args(
+                m,
+                OPERATION::SHRED {
+                    num_runs: *m.get_one("ow_runs").unwrap(),
+                },
+            )?,
+        ),
+        Some(("resume", m)) => resume(m.get_one::<String>("state"), dry_run),
+        Some(("apply", m)) => apply(m.get_one::<String>("plan").unwrap(), dry_run),
+        Some(("verify-report", m)) => verify_report(m.get_one::<String>("report").unwrap()),
+        Some(("protect", m)) => MarkOperation::operate(
+            get_files_from_sub(m),
+            OPERATION::PROTECT,
+            m.get_flag("xattr"),
+            Executor::new(dry_run),
+        ),
+        Some(("unprotect", m)) => MarkOperation::operate(
+            get_files_from_sub(m),
+            OPERATION::UNPROTECT,
+            false,
+            Executor::new(dry_run),
+        ),
+        Some(("wipe-free", m)) => WipeFreeOperation::operate(
+            m.get_one::<String>("dir").unwrap(),
+            WipeFreeOptions {
+                ow_options: OverwriteOptions {
+                    direct_io: m.get_flag("direct"),
+                    pattern: pattern_from_arg(m.get_one::<String>("pattern").unwrap()),
+                    buffer_size: *m.get_one::<u64>("buffer_size").unwrap() as usize,
+                    backend: backend_from_flag(m.get_flag("io_uring")),
+                    bwlimit: bwlimit_from_args(m),
+                    ..Default::default()
+                },
+                reserve: *m.get_one("reserve").unwrap(),
+            },
+            Executor::new(dry_run),
+        ),
+        Some(("search", m)) => {
+            let op = search_op_from_arg(m.get_one::<String>("command").unwrap());
+            SearchOperation::new(
+                op,
+                m.get_one::<String>("target").unwrap(),
+                m.get_one::<String>("dir").unwrap(),
+                protection_from_args(m, op)?,
+                Executor::new(dry_run),
+            )
+            .operate()
+        }
         Some(("list", m)) => BasicOperations::list(m.get_one("search")),
 
         _ => Ok(()),
+    }
+}
+
+fn delete_operation_from_args(m: &ArgMatches) -> DeleteOperation {
+    DeleteOperation::new(
+        *m.get_one("jobs").unwrap(),
+        m.get_flag("keep_going"),
+        Executor::from_args(m),
+    )
+}
+
+fn purge_shred_options_from_args(m: &ArgMatches) -> Option<ShredOptions> {
+    m.get_flag("shred").then(|| ShredOptions {
+        ow_options: OverwriteOptions {
+            runs: *m.get_one("ow_runs").unwrap(),
+            verify: m.get_flag("verify"),
+            slack: m.get_flag("slack"),
+            pattern: pattern_from_arg(m.get_one::<String>("pattern").unwrap()),
+            ..Default::default()
+        },
+        remove_mode: remove_mode_from_arg(m.get_one::<String>("remove").unwrap()),
+        force_links: m.get_flag("force_links"),
+        ..Default::default()
+    })
+}
+
+fn shred_operation_from_args(m: &ArgMatches) -> ShredOperation {
+    ShredOperation::new(
+        ShredOptions {
+            ow_options: OverwriteOptions {
+                runs: *m.get_one("ow_runs").unwrap(),
+                direct_io: m.get_flag("direct"),
+                verify: m.get_flag("verify"),
+                slack: m.get_flag("slack"),
+                pattern: pattern_from_arg(m.get_one::<String>("pattern").unwrap()),
+                buffer_size: *m.get_one::<u64>("buffer_size").unwrap() as usize,
+                backend: backend_from_flag(m.get_flag("io_uring")),
+                bwlimit: bwlimit_from_args(m),
+                ..Default::default()
+            },
+            remove_mode: remove_mode_from_arg(m.get_one::<String>("remove").unwrap()),
+            force_links: m.get_flag("force_links"),
+            allow_devices: m.get_flag("allow_devices"),
+            discard: m.get_flag("discard"),
+        },
+        *m.get_one("jobs").unwrap(),
+        m.get_flag("strict"),
+        m.get_flag("keep_going"),
+        Executor::from_args(m),
+        m.get_one::<String>("report").map(|p| ReportTarget {
+            path: PathBuf::from(p),
+            hash: m.get_flag("report_hash"),
+            builder: ReportBuilder::default(),
+        }),
+    )
+}
+
+fn search_op_from_arg(arg: &str) -> OPERATION {
+    match arg {
+        "t" => OPERATION::TRASH,
+        "d" => OPERATION::DELETE,
+        "s" => OPERATION::SHRED { num_runs: 1 },
+        //clap only accepts the values above
+        _ => unreachable!(),
+    }
+}
+
+fn remove_mode_from_arg(arg: &str) -> RemoveMode {
+    match arg {
+        "unlink" => RemoveMode::Unlink,
+        "wipe" => RemoveMode::Wipe,
+        "wipesync" => RemoveMode::WipeSync,
+        //clap only accepts the values above
+        _ => unreachable!(),
+    }
+}
+
+fn pattern_from_arg(arg: &str) -> OverwritePattern {
+    match arg {
+        "zeroes" => OverwritePattern::Zeroes,
+        "ones" => OverwritePattern::Ones,
+        "random" => OverwritePattern::Random,
+        //clap only accepts the values above
+        _ => unreachable!(),
+    }
+}
+
+fn backend_from_flag(io_uring: bool) -> WriteBackend {
+    match io_uring {
+        true => WriteBackend::IoUring,
+        false => WriteBackend::Positional,
+    }
+}
+
+//Whether to recurse is only known once the paths have been checked
+fn walk_options_from_args(
+    args: &ArgMatches,
+    operation: OPERATION,
+) -> Result<WalkOptions, OperationError> {
+    let symlinks = if args.get_flag("follow_symlinks") {
+        SymlinkPolicy::Always
+    } else if args.get_flag("follow_command_line") {
+        SymlinkPolicy::CommandLine
+    } else {
+        SymlinkPolicy::Never
     };
+
+    Ok(WalkOptions {
+        recurse: false,
+        symlinks,
+        one_file_system: one_file_system_from_args(args),
+        protection: protection_from_args(args, operation)?,
+        filter: filter_from_args(args),
+    })
+}
+
+//Whichever of -x and --no-one-file-system comes last wins, and either of them beats RRC_ONE_FILE_SYSTEM. Clap
+//can't override a flag set from the environment, so this is worked out from where they were given
+fn one_file_system_from_args(args: &ArgMatches) -> bool {
+    if !args.get_flag("no_one_file_system") {
+        return args.get_flag("one_file_system");
+    }
+    let last_given = |id| match args.value_source(id) {
+        Some(ValueSource::CommandLine) => args.indices_of(id).and_then(|mut i| i.next_back()),
+        _ => None,
+    };
+    last_given("one_file_system") > last_given("no_one_file_system")
+}
+
+fn filter_from_args(args: &ArgMatches) -> Filter {
+    let patterns = |id| {
+        args.get_many::<Pattern>(id)
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect()
+    };
+
+    Filter {
+        include: patterns("include"),
+        exclude: patterns("exclude"),
+        types: args
+            .get_many::<EntryType>("entry_type")
+            .into_iter()
+            .flatten()
+            .copied()
+            .collect(),
+        min_size: args.get_one("min_size").copied(),
+        max_size: args.get_one("max_size").copied(),
+        older_than: args.get_one("older_than").copied(),
+        newer_than: args.get_one("newer_than").copied(),
+        min_depth: args.get_one("min_depth").copied(),
+        max_depth: args.get_one("max_depth").copied(),
+    }
+}
+
+//Protected patterns are read from the config file as well as the command line
+fn protection_from_args(
+    args: &ArgMatches,
+    operation: OPERATION,
+) -> Result<Protection, OperationError> {
+    if args.get_flag("no_preserve_root") {
+        return Ok(Protection::none());
+    }
+
+    let mut patterns = match protect::default_patterns_path() {
+        Some(path) => match protect::read_patterns(&path) {
+            Ok(patterns) => patterns,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                return Err(OperationError::new(
+                    Box::new(e),
+                    operation,
+                    Some(path_to_string(&path)),
+                ))
+            }
+        },
+        None => Vec::new(),
+    };
+    patterns.extend(
+        args.get_many::<String>("protect")
+            .into_iter()
+            .flatten()
+            .cloned(),
+    );
+
+    let mut protection = Protection::builtin();
+    for pattern in patterns {
+        protection
+            .add_pattern(&pattern)
+            .map_err(|e| OperationError::new(Box::new(e), operation, Some(pattern)))?;
+    }
+    for path in args
+        .get_many::<String>("allow_protected")
+        .into_iter()
+        .flatten()
+    {
+        protection.allow(Path::new(path));
+    }
+    Ok(protection)
+}
+
+fn bwlimit_from_args(args: &ArgMatches) -> Option<Arc<TokenBucket>> {
+    args.get_one::<u64>("bwlimit")
+        .map(|rate| Arc::new(TokenBucket::new(*rate)))
+}
+
+//Not every subcommand takes --ionice, and failing to set it isn't worth stopping the operation over
+fn apply_io_priority(args: &ArgMatches) {
+    if let Ok(Some(priority)) = args.try_get_one::<IoPriority>("ionice") {
+        if let Err(e) = throttle::set_io_priority(*priority) {
+            output::print_io_priority_warning(&e);
+        }
+    }
 }
 
 fn get_files_from_sub(args: &ArgMatches) -> Vec<String> {
@@ -121,7 +423,13 @@ impl BasicOperations {
         trash_list.print();
         Ok(())
     }
-    pub fn purge(files: Vec<String>, all_files: bool) -> Result<(), OperationError> {
+    ///Purges files from the trash, shredding them first if shred options are given
+    pub fn purge(
+        files: Vec<String>,
+        all_files: bool,
+        shred_options: Option<ShredOptions>,
+        executor: Executor,
+    ) -> Result<(), OperationError> {
         let items: Vec<TrashItem>;
         let pb = OpSpinner::default(OPERATION::PURGE { all_files });
 
@@ -138,12 +446,45 @@ impl BasicOperations {
             })
         }
 
+        Self::purge_items(items, all_files, shred_options, executor, pb)
+    }
+
+    fn purge_items(
+        items: Vec<TrashItem>,
+        all_files: bool,
+        shred_options: Option<ShredOptions>,
+        executor: Executor,
+        pb: OpSpinner,
+    ) -> Result<(), OperationError> {
         for file in &items {
             pb.set_file_str(file.name.clone());
-            purge_all(vec![file]).unwrap();
+            match &shred_options {
+                Some(o) => {
+                    let operation = OPERATION::SHRED {
+                        num_runs: o.ow_options.runs,
+                    };
+                    executor
+                        .on_trash_item(operation, file, || shred::shred_trash_item(file, o))
+                        .map_err(|e| {
+                            pb.finish();
+                            OperationError::new(
+                                Box::new(e),
+                                OPERATION::PURGE { all_files },
+                                Some(file.name.clone()),
+                            )
+                        })?;
+                }
+                None => {
+                    executor
+                        .on_trash_item(OPERATION::PURGE { all_files }, file, || {
+                            purge_all(vec![file])
+                        })
+                        .unwrap();
+                }
+            }
         }
 
-        pb.auto_finish(items.len());
+        executor.finish(&pb, items.len());
         Ok(())
     }
 }
@@ -151,7 +492,12 @@ impl BasicOperations {
 struct TrashOperation;
 
 impl TrashOperation {
-    pub fn trash(files: Vec<String>) -> Result<(), OperationError> {
+    ///Moves the paths to the trash whole, or walks them and trashes what the filters select if there are any
+    pub fn trash(
+        files: Vec<String>,
+        walk: WalkOptions,
+        executor: Executor,
+    ) -> Result<(), OperationError> {
         let pb = OpSpinner::default(OPERATION::TRASH);
 
         let filtered_path_strings = files::get_existent_paths(&files, |s| {
@@ -160,11 +506,54 @@ impl TrashOperation {
         let paths = files::path_vec_from_string_vec(filtered_path_strings);
         let len = paths.len();
 
+        //Trashing doesn't walk the paths unless there are filters, so they're checked here instead. The whole tree
+        //is moved, so anything marked inside it counts too
+        let protection = &walk.protection;
+        let filtered = walk.filter != Filter::default();
+        for path in &paths {
+            let checked = match filtered {
+                true => Ok(()),
+                false => protection
+                    .check(path, false)
+                    .and_then(|_| protection.check_tree(path)),
+            };
+            if let Err(e) = checked {
+                output::print_protected_hint(&e);
+                return Err(OperationError::new(
+                    Box::new(e),
+                    OPERATION::TRASH,
+                    Some(files::path_to_string(path)),
+                ));
+            }
+        }
+
         pb.start();
 
+        if filtered {
+            let mut op = FilteredTrash {
+                pb: &pb,
+                executor: &executor,
+            };
+            return match rrc_lib::recurse_on_paths(&mut op, paths, &walk) {
+                Ok(c) => {
+                    executor.finish(&pb, c);
+                    Ok(())
+                }
+                Err(e) => {
+                    pb.finish();
+                    let file = e.file.clone();
+                    Err(OperationError::new(
+                        Box::new(e),
+                        OPERATION::TRASH,
+                        Some(file),
+                    ))
+                }
+            };
+        }
+
         for path in paths {
             pb.set_file_path(path);
-            match trash::delete_all([path]) {
+            match executor.on_tree(OPERATION::TRASH, path, || trash::delete_all([path])) {
                 Ok(_) => {}
                 Err(e) => {
                     return Err(OperationError::new(
@@ -176,46 +565,103 @@ impl TrashOperation {
             }
         }
 
-        pb.auto_finish(len);
+        executor.finish(&pb, len);
 
         Ok(())
     }
+}
 
-    pub fn trash_single(path: &PathBuf) -> Result<(), OperationError> {
-        match trash::delete_all([path]) {
-            Ok(_) => return Ok(()),
-            Err(e) => {
-                return Err(OperationError::new(
-                    Box::new(e),
-                    OPERATION::TRASH,
-                    Some(files::path_to_string(path)),
-                ))
+//Trashes what the filters select one path at a time, so that a directory is only trashed once everything inside
+//it has been
+struct FilteredTrash<'a> {
+    pb: &'a OpSpinner,
+    executor: &'a Executor,
+}
+
+impl RecursiveCallback for FilteredTrash<'_> {
+    fn cb(&mut self, path: &Path) -> Result<bool, FileErr> {
+        self.executor
+            .on_path(OPERATION::TRASH, path, || trash::delete(path))
+            .map_err(|e| FileErr::map(io::Error::other(e), path))?;
+        Ok(true)
+    }
+
+    fn display_cb(&mut self, path: &Path, _is_dir: bool) -> bool {
+        self.pb.set_file_path(path);
+        true
+    }
+}
+
+struct MarkOperation;
+
+impl MarkOperation {
+    ///Adds protection markers to paths, or removes them for UNPROTECT
+    fn operate(
+        files: Vec<String>,
+        operation: OPERATION,
+        xattr: bool,
+        executor: Executor,
+    ) -> Result<(), OperationError> {
+        let pb = OpSpinner::default(operation);
+
+        let filtered_path_strings = get_existent_paths(&files, |s| pb.print_no_file_warn(s));
+        let paths = path_vec_from_string_vec(filtered_path_strings);
+
+        pb.start();
+        let mut count = 0;
+        for path in paths {
+            pb.set_file_path(path);
+            let res = executor.on_path(operation, path, || match operation {
+                OPERATION::PROTECT => protect::mark(path, xattr).map(|_| true),
+                _ => protect::unmark(path),
+            });
+            match res {
+                Ok(Some(false)) => pb.print_not_marked_warn(path),
+                Ok(_) => count += 1,
+                Err(e) => {
+                    return Err(OperationError::new(
+                        Box::new(e),
+                        operation,
+                        Some(path_to_string(path)),
+                    ))
+                }
             }
         }
+
+        executor.finish(&pb, count);
+        Ok(())
     }
 }
 
 struct RestoreOperation;
 
 impl RestoreOperation {
-    fn operate(files: Vec<String>) -> Result<(), OperationError> {
+    fn operate(files: Vec<String>, executor: Executor) -> Result<(), OperationError> {
         let pb = OpSpinner::default(OPERATION::RESTORE);
 
-        let mut items = get_existent_trash_items(&files, output::run_conflict_prompt, |f| {
+        let items = get_existent_trash_items(&files, output::run_conflict_prompt, |f| {
             pb.print_no_file_warn(f);
         });
 
+        Self::restore(items, pb, executor)
+    }
+
+    fn restore(
+        mut items: Vec<TrashItem>,
+        pb: OpSpinner,
+        executor: Executor,
+    ) -> Result<(), OperationError> {
         if items.len() > 1 {
             pb.start();
         }
 
         loop {
             let len_before_attempt = items.len();
-            let res = Self::attempt_restore(&mut items, &pb);
+            let res = Self::attempt_restore(&mut items, &pb, &executor);
             match res {
                 Ok(s) => {
                     if s {
-                        pb.auto_finish(len_before_attempt);
+                        executor.finish(&pb, len_before_attempt);
                         return Ok(());
                     }
                 }
@@ -228,10 +674,16 @@ impl RestoreOperation {
 
     ///Attemps to restore the file. Handles any errors that might occur (or paths that don't actually exist in the trash)
     /// Returns a Ok(Some()) modified copy of the input files if changes had to be made, otherwise it returns Ok(None)
-    fn attempt_restore(files: &mut Vec<TrashItem>, pb: &OpSpinner) -> Result<bool, OperationError> {
+    fn attempt_restore(
+        files: &mut Vec<TrashItem>,
+        pb: &OpSpinner,
+        executor: &Executor,
+    ) -> Result<bool, OperationError> {
         for file in files.clone() {
             pb.set_file_str(file.name.clone());
-            match trash::os_limited::restore_all([file.clone()]) {
+            match executor.on_trash_item(OPERATION::RESTORE, &file, || {
+                trash::os_limited::restore_all([file.clone()])
+            }) {
                 Ok(_) => util::remove_from_vec(files, &file),
                 Err(e) => {
                     util::handle_collision_item(e, files, &file).map_err(|err| {
@@ -250,32 +702,73 @@ impl RestoreOperation {
 //fast that I doubt the performance hit will matter
 struct DeleteOperation {
     pb: OpSpinner,
+    jobs: usize,
+    resume: ResumeTracker,
+    interrupt: Arc<AtomicBool>,
+    skipped_mounts: Mutex<Vec<PathBuf>>,
+    skipped_marked: Mutex<Vec<PathBuf>>,
+    failures: FailureLog,
+    executor: Executor,
 }
 
-impl Default for DeleteOperation {
-    fn default() -> DeleteOperation {
+impl DeleteOperation {
+    fn new(jobs: usize, keep_going: bool, executor: Executor) -> DeleteOperation {
         DeleteOperation {
             pb: OpSpinner::default(OPERATION::DELETE),
+            jobs,
+            resume: ResumeTracker::default(),
+            interrupt: Arc::default(),
+            skipped_mounts: Mutex::default(),
+            skipped_marked: Mutex::default(),
+            failures: FailureLog::new(OPERATION::DELETE, keep_going),
+            executor,
         }
     }
-}
 
-impl DeleteOperation {
-    fn operate(&mut self, files: Vec<String>, recurse_default: bool) -> Result<(), OperationError> {
+    fn operate(
+        &mut self,
+        files: Vec<String>,
+        recurse_default: bool,
+        mut walk: WalkOptions,
+    ) -> Result<(), OperationError> {
         let string_paths = get_existent_paths(&files, |f| self.pb.print_no_file_warn(f.as_str()));
 
         let paths = path_vec_from_string_vec(string_paths);
-        let recurse = check_recursion(&paths, recurse_default);
+        walk.recurse = check_recursion(&paths, recurse_default, walk.symlinks);
+
+        self.run(paths, walk)
+    }
 
+    fn run(&mut self, paths: Vec<&Path>, walk: WalkOptions) -> Result<(), OperationError> {
+        //Only installed now so that Ctrl-C still quits during the recursion prompt
+        self.interrupt = interrupt_flag();
         self.pb.start();
 
-        match rrc_lib::recurse_on_paths(self, paths, recurse) {
+        let res = match self.jobs {
+            0 | 1 => rrc_lib::recurse_on_paths(self, paths.clone(), &walk),
+            jobs => rrc_lib::recurse_on_paths_parallel(&*self, paths.clone(), &walk, jobs),
+        };
+        if self.interrupt.load(Ordering::Relaxed) && !self.executor.dry_run() {
+            self.pb.finish();
+            self.resume
+                .save_interrupted(OPERATION::DELETE, &paths, walk.recurse);
+        }
+
+        match res {
             Ok(c) => {
-                self.pb.auto_finish(c);
-                Ok(())
+                if !self.interrupt.load(Ordering::Relaxed) {
+                    self.executor.finish(&self.pb, c);
+                    self.resume.finish();
+                }
+                output::print_skipped_mounts(&self.skipped_mounts.lock().unwrap());
+                output::print_skipped_marked(&self.skipped_marked.lock().unwrap());
+                self.failures.finish()
             }
             Err(e) => {
                 self.pb.finish();
+                if let Some(protected) = e.protected() {
+                    output::print_protected_hint(protected);
+                }
                 let file = e.file.clone();
                 Err(OperationError::new(
                     Box::new(e),
@@ -287,55 +780,363 @@ impl DeleteOperation {
     }
 }
 
-impl RecursiveCallback for DeleteOperation {
-    fn cb(&mut self, path: &PathBuf) -> Result<bool, FileErr> {
-        match files::remove_file_or_empty_dir(path) {
-            Ok(()) => return Ok(true),
-            Err(e) => return Err(FileErr::map(e, path)),
+impl DeleteOperation {
+    fn remove(
+        &self,
+        path: &Path,
+        remove: impl FnOnce() -> io::Result<()>,
+    ) -> Result<bool, FileErr> {
+        if self.interrupt.load(Ordering::Relaxed) {
+            return Ok(false);
+        }
+        match self.executor.on_path(OPERATION::DELETE, path, remove) {
+            Ok(_) => {
+                self.resume.complete(path);
+                Ok(true)
+            }
+            Err(e) => Err(FileErr::map(e, path)),
         }
     }
+}
+
+impl RecursiveCallback for DeleteOperation {
+    fn cb(&mut self, path: &Path) -> Result<bool, FileErr> {
+        ParallelCallback::cb(self, path)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn cb_at(&mut self, entry: &EntryAt) -> Result<bool, FileErr> {
+        ParallelCallback::cb_at(self, entry)
+    }
 
-    fn display_cb(&mut self, path: &PathBuf, _is_dir: bool) -> bool {
+    fn display_cb(&mut self, path: &Path, is_dir: bool) -> bool {
+        ParallelCallback::display_cb(self, path, is_dir)
+    }
+
+    fn mount_skipped(&mut self, path: &Path) {
+        ParallelCallback::mount_skipped(self, path)
+    }
+
+    fn protected_skipped(&mut self, protected: &ProtectedPath) {
+        ParallelCallback::protected_skipped(self, protected)
+    }
+
+    fn failed(&mut self, err: FileErr) -> Result<(), FileErr> {
+        ParallelCallback::failed(self, err)
+    }
+}
+
+impl ParallelCallback for DeleteOperation {
+    fn cb(&self, path: &Path) -> Result<bool, FileErr> {
+        self.remove(path, || files::remove_file_or_empty_dir(path))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn cb_at(&self, entry: &EntryAt) -> Result<bool, FileErr> {
+        self.remove(entry.path(), || entry.remove())
+    }
+
+    fn display_cb(&self, path: &Path, _is_dir: bool) -> bool {
         let path_name = files::path_to_string(path);
 
         self.pb.set_file_str(path_name);
         true
     }
+
+    fn mount_skipped(&self, path: &Path) {
+        self.pb.print_mount_skipped_warn(path);
+        self.skipped_mounts.lock().unwrap().push(path.to_path_buf());
+    }
+
+    fn protected_skipped(&self, protected: &ProtectedPath) {
+        self.pb.print_protected_skipped_warn(protected);
+        if let ProtectedKind::Marked(marked) = &protected.kind {
+            self.skipped_marked
+                .lock()
+                .unwrap()
+                .extend_from_slice(marked);
+        }
+    }
+
+    fn failed(&self, err: FileErr) -> Result<(), FileErr> {
+        self.failures.record(&self.pb, err)
+    }
 }
 
 struct ShredOperation {
     pb: OpSpinner,
-    num_runs: usize,
+    progress: ShredProgress,
+    options: ShredOptions,
+    jobs: usize,
+    strict: bool,
+    //Counted atomically since files can be shredded from several threads
+    linked_files: AtomicUsize,
+    sparse_files: AtomicUsize,
+    undiscarded_files: AtomicUsize,
+    report: Option<ReportTarget>,
+    resume: ResumeTracker,
+    interrupt: Arc<AtomicBool>,
+    skipped_mounts: Mutex<Vec<PathBuf>>,
+    skipped_marked: Mutex<Vec<PathBuf>>,
+    //Devices the shred has been on, and whether shredding there is refused. A tree can cross onto filesystems
+    //which none of the paths given to the shred are on, so each one is checked the first time it's seen
+    devices: Mutex<HashMap<u64, bool>>,
+    failures: FailureLog,
+    executor: Executor,
+}
+
+///Where the certificate of destruction for a shred gets written, and the records going into it
+struct ReportTarget {
+    path: PathBuf,
+    hash: bool,
+    builder: ReportBuilder,
 }
 
 impl ShredOperation {
-    fn new(num_runs: usize) -> ShredOperation {
+    fn new(
+        options: ShredOptions,
+        jobs: usize,
+        strict: bool,
+        keep_going: bool,
+        executor: Executor,
+        report: Option<ReportTarget>,
+    ) -> ShredOperation {
+        let operation = OPERATION::SHRED {
+            num_runs: options.ow_options.runs,
+        };
+        let pb = OpSpinner::default(operation);
         ShredOperation {
-            pb: OpSpinner::default(OPERATION::SHRED { num_runs }),
-            num_runs,
+            progress: ShredProgress::new(&pb, jobs <= 1),
+            pb,
+            options,
+            jobs,
+            strict,
+            linked_files: AtomicUsize::new(0),
+            sparse_files: AtomicUsize::new(0),
+            undiscarded_files: AtomicUsize::new(0),
+            report,
+            resume: ResumeTracker::default(),
+            interrupt: Arc::default(),
+            skipped_mounts: Mutex::default(),
+            skipped_marked: Mutex::default(),
+            devices: Mutex::default(),
+            failures: FailureLog::new(operation, keep_going),
+            executor,
+        }
+    }
+
+    fn record(&self, shredded: ShreddedFile) {
+        if shredded.links > 1 {
+            self.linked_files.fetch_add(1, Ordering::Relaxed);
+        }
+        if shredded.sparse {
+            self.sparse_files.fetch_add(1, Ordering::Relaxed);
+        }
+        if shredded.discarded == Some(false) {
+            self.undiscarded_files.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    //Shreds a path with the function given, which reaches the file either by its path or relative to the
+    //directory it was found in
+    fn shred(
+        &self,
+        path: &Path,
+        metadata: io::Result<fs::Metadata>,
+        shred: impl FnOnce(&ShredOptions) -> io::Result<ShreddedFile>,
+    ) -> Result<bool, FileErr> {
+        if self.interrupt.load(Ordering::Relaxed) {
+            return Ok(false);
+        }
+        if self.refuses_filesystem(path, metadata) {
+            return Err(FileErr::map(
+                io::Error::other(
+                    "refusing to shred a file on a filesystem where overwriting it may not be effective",
+                ),
+                path,
+            ));
+        }
+        //Devices aren't removed after being shredded, so a resumed shred has to skip them itself
+        if self.resume.is_completed(path) {
+            return Ok(true);
+        }
+
+        let resumed_options;
+        let options = match self.resume.first_pass(path) {
+            1 => &self.options,
+            first_pass => {
+                resumed_options = ShredOptions {
+                    ow_options: OverwriteOptions {
+                        first_pass,
+                        ..self.options.ow_options.clone()
+                    },
+                    ..self.options.clone()
+                };
+                &resumed_options
+            }
+        };
+
+        let started = chrono::Utc::now();
+        let operation = OPERATION::SHRED {
+            num_runs: options.ow_options.runs,
+        };
+        let shredded = match self.executor.on_path(operation, path, || shred(options)) {
+            Ok(Some(s)) => s,
+            Ok(None) => return Ok(true),
+            Err(e) => match files::interrupted_pass(&e) {
+                Some(pass) => {
+                    self.resume.interrupt(path, pass);
+                    return Ok(false);
+                }
+                None => return Err(FileErr::map(e, path)),
+            },
+        };
+        self.resume.complete(path);
+        self.record(shredded);
+        if let Some(target) = &self.report {
+            target
+                .builder
+                .record(path, &shredded, &self.options, started);
         }
+
+        Ok(true)
     }
 
-    fn operate(&mut self, files: Vec<String>, recurse_default: bool) -> Result<(), OperationError> {
+    //Checks the filesystem of a file the first time its device is seen, warning about it if overwriting there can't
+    //be relied on. Returns whether the file is to be refused because of that
+    fn refuses_filesystem(&self, path: &Path, metadata: io::Result<fs::Metadata>) -> bool {
+        let Some(device) = device_of(metadata) else {
+            return false;
+        };
+        let mut devices = self.devices.lock().unwrap();
+        if let Some(refused) = devices.get(&device) {
+            return *refused;
+        }
+        let concern = filesystem::overwrite_concerns(&[path]).pop();
+        if let Some(concern) = &concern {
+            self.pb.print_overwrite_concern_warn(concern);
+        }
+        let refused = self.strict && concern.is_some();
+        devices.insert(device, refused);
+        refused
+    }
+
+    fn operate(
+        &mut self,
+        files: Vec<String>,
+        recurse_default: bool,
+        mut walk: WalkOptions,
+    ) -> Result<(), OperationError> {
         let string_paths = get_existent_paths(&files, |f| self.pb.print_no_file_warn(f));
 
         let paths = path_vec_from_string_vec(string_paths);
-        let recurse = check_recursion(&paths, recurse_default);
+        walk.recurse = check_recursion(&paths, recurse_default, walk.symlinks);
+
+        let concerns = filesystem::overwrite_concerns(&paths);
+        output::print_overwrite_concerns(&concerns, self.strict);
+        if self.strict && !concerns.is_empty() {
+            return Err(OperationError::new(
+                Box::new(io::Error::other(
+                    "refusing to shred files on filesystems where overwriting them may not be effective",
+                )),
+                OPERATION::SHRED {
+                    num_runs: self.options.ow_options.runs,
+                },
+                Some(path_to_string(&concerns[0].mount_point)),
+            ));
+        }
+        //Whatever there was to say about the filesystems of the paths themselves has just been said
+        for path in &paths {
+            if let Some(device) = device_of(fs::symlink_metadata(path)) {
+                self.devices.get_mut().unwrap().insert(device, false);
+            }
+        }
+
+        self.run(paths, walk)
+    }
+
+    fn run(&mut self, paths: Vec<&Path>, walk: WalkOptions) -> Result<(), OperationError> {
+        //Only installed now so that Ctrl-C still quits during the recursion prompt
+        self.interrupt = interrupt_flag();
+        self.options.ow_options.cancel = Some(self.interrupt.clone());
 
+        self.progress
+            .set_total(files::total_file_size(&paths, &walk) * self.options.ow_options.runs as u64);
+        self.progress
+            .set_limit(self.options.ow_options.bwlimit.as_ref().map(|b| b.rate()));
         self.pb.start();
 
-        match rrc_lib::recurse_on_paths(self, paths, recurse) {
+        let res = match self.jobs {
+            0 | 1 => rrc_lib::recurse_on_paths(self, paths.clone(), &walk),
+            jobs => rrc_lib::recurse_on_paths_parallel(&*self, paths.clone(), &walk, jobs),
+        };
+        self.progress.finish();
+
+        let interrupted = self.interrupt.load(Ordering::Relaxed);
+        if interrupted && !self.executor.dry_run() {
+            self.pb.finish();
+            self.resume.save_interrupted(
+                OPERATION::SHRED {
+                    num_runs: self.options.ow_options.runs,
+                },
+                &paths,
+                walk.recurse,
+            );
+        }
+
+        //The report is written even if the shred failed part way, since it still records what was destroyed.
+        //A dry run destroys nothing, so there's nothing to report
+        let report = self.report.take().filter(|_| !self.executor.dry_run());
+        let report_res = match report {
+            Some(target) => match target
+                .builder
+                .finish(target.hash)
+                .and_then(|r| r.write(&target.path))
+            {
+                Ok(()) => Ok(Some(target.path)),
+                Err(e) => Err((e, target.path)),
+            },
+            None => Ok(None),
+        };
+
+        match res {
             Ok(c) => {
-                self.pb.auto_finish(c);
-                Ok(())
+                if !interrupted {
+                    self.executor.finish(&self.pb, c);
+                    self.resume.finish();
+                }
+                output::print_shred_notes(
+                    self.linked_files.load(Ordering::Relaxed),
+                    self.sparse_files.load(Ordering::Relaxed),
+                    self.undiscarded_files.load(Ordering::Relaxed),
+                );
+                output::print_skipped_mounts(&self.skipped_mounts.lock().unwrap());
+                output::print_skipped_marked(&self.skipped_marked.lock().unwrap());
+                match report_res {
+                    Ok(Some(path)) => {
+                        output::print_report_written(&path);
+                        self.failures.finish()
+                    }
+                    Ok(None) => self.failures.finish(),
+                    Err((e, path)) => Err(OperationError::new(
+                        Box::new(e),
+                        OPERATION::SHRED {
+                            num_runs: self.options.ow_options.runs,
+                        },
+                        Some(path_to_string(path)),
+                    )),
+                }
             }
             Err(e) => {
                 self.pb.finish();
+                if let Some(protected) = e.protected() {
+                    output::print_protected_hint(protected);
+                }
                 let file = e.file.clone();
                 Err(OperationError::new(
                     Box::new(e),
                     OPERATION::SHRED {
-                        num_runs: self.num_runs,
+                        num_runs: self.options.ow_options.runs,
                     },
                     Some(file),
                 ))
@@ -345,80 +1146,188 @@ impl ShredOperation {
 }
 
 impl RecursiveCallback for ShredOperation {
-    fn cb(&mut self, path: &PathBuf) -> Result<bool, FileErr> {
-        if !path.is_dir() {
-            let mut file = OpenOptions::new()
-                .write(true)
-                .open(path)
-                .map_err(|e| FileErr::map(e, path))?;
-            files::overwrite_file(&mut file, self.num_runs).map_err(|e| FileErr::map(e, path))?;
-        }
+    fn cb(&mut self, path: &Path) -> Result<bool, FileErr> {
+        ParallelCallback::cb(self, path)
+    }
 
-        files::remove_file_or_empty_dir(path).map_err(|e| FileErr::map(e, path))?;
+    #[cfg(target_os = "linux")]
+    fn cb_at(&mut self, entry: &EntryAt) -> Result<bool, FileErr> {
+        ParallelCallback::cb_at(self, entry)
+    }
 
-        Ok(true)
+    fn display_cb(&mut self, path: &Path, is_dir: bool) -> bool {
+        ParallelCallback::display_cb(self, path, is_dir)
+    }
+
+    fn mount_skipped(&mut self, path: &Path) {
+        ParallelCallback::mount_skipped(self, path)
+    }
+
+    fn protected_skipped(&mut self, protected: &ProtectedPath) {
+        ParallelCallback::protected_skipped(self, protected)
     }
 
-    fn display_cb(&mut self, path: &PathBuf, _is_dir: bool) -> bool {
+    fn failed(&mut self, err: FileErr) -> Result<(), FileErr> {
+        ParallelCallback::failed(self, err)
+    }
+}
+
+impl ParallelCallback for ShredOperation {
+    fn cb(&self, path: &Path) -> Result<bool, FileErr> {
+        self.shred(path, fs::symlink_metadata(path), |options| {
+            shred::shred_path(path, options, &mut &self.progress)
+        })
+    }
+
+    #[cfg(target_os = "linux")]
+    fn cb_at(&self, entry: &EntryAt) -> Result<bool, FileErr> {
+        self.shred(entry.path(), entry.symlink_metadata(), |options| {
+            shred::shred_entry(entry, options, &mut &self.progress)
+        })
+    }
+
+    fn display_cb(&self, path: &Path, _is_dir: bool) -> bool {
         let path_name = files::path_to_string(path);
         self.pb.set_file_str(path_name);
 
         true
     }
+
+    fn mount_skipped(&self, path: &Path) {
+        self.pb.print_mount_skipped_warn(path);
+        self.skipped_mounts.lock().unwrap().push(path.to_path_buf());
+    }
+
+    fn protected_skipped(&self, protected: &ProtectedPath) {
+        self.pb.print_protected_skipped_warn(protected);
+        if let ProtectedKind::Marked(marked) = &protected.kind {
+            self.skipped_marked
+                .lock()
+                .unwrap()
+                .extend_from_slice(marked);
+        }
+    }
+
+    fn failed(&self, err: FileErr) -> Result<(), FileErr> {
+        self.failures.record(&self.pb, err)
+    }
+}
+
+struct WipeFreeOperation;
+
+impl WipeFreeOperation {
+    fn operate(
+        dir: &str,
+        options: WipeFreeOptions,
+        executor: Executor,
+    ) -> Result<(), OperationError> {
+        let op_error =
+            |e: Box<dyn Error>| OperationError::new(e, OPERATION::WIPEFREE, Some(dir.to_string()));
+
+        //The fill files have to be removed on the way out, so Ctrl-C only asks the wipe to stop, and asking again
+        //waits for them to be removed
+        let cancel = interrupt_flag();
+        let cleanup = CLEANUP.read().unwrap_or_else(PoisonError::into_inner);
+
+        let pb = OpSpinner::default(OPERATION::WIPEFREE);
+        let progress = ShredProgress::new(&pb, false);
+        progress.set_limit(options.ow_options.bwlimit.as_ref().map(|b| b.rate()));
+        pb.set_file_str(dir.to_string());
+        pb.start();
+
+        let res = executor.execute(
+            OPERATION::WIPEFREE,
+            || dir.to_string(),
+            || {
+                filesystem::free_space(Path::new(dir))
+                    .map_or(0, |f| f.saturating_sub(options.reserve))
+            },
+            || None,
+            || freespace::wipe_free_space(Path::new(dir), &options, &mut &progress, &cancel),
+        );
+        drop(cleanup);
+        progress.finish();
+        pb.finish();
+
+        match res {
+            Ok(Some(wiped)) => {
+                output::print_wipe_free_result(&wiped);
+                Ok(())
+            }
+            Ok(None) => {
+                executor.finish(&pb, 0);
+                Ok(())
+            }
+            Err(e) => Err(op_error(Box::new(e))),
+        }
+    }
 }
 
 struct SearchOperation {
     op: OPERATION,
     target: String,
     directory: String,
-    pb: OpSpinner,
     operate_curr_file: bool,
+    //The search itself only reads, so this is only checked against the matches picked to run the operation on
+    protection: Protection,
+    executor: Executor,
 }
 
 impl SearchOperation {
-    pub fn new(op_arg: &String, target: &String, directory: &String) -> SearchOperation {
-        let op = match op_arg.as_str() {
-            "t" => OPERATION::TRASH,
-            "d" => OPERATION::DELETE,
-            "s" => OPERATION::SHRED { num_runs: 1 }, //if none of these match, clap hasn't parsed our arguments properly and nothing can be trusted.
-            _ => panic!(),
-        };
-
+    pub fn new(
+        op: OPERATION,
+        target: &str,
+        directory: &str,
+        protection: Protection,
+        executor: Executor,
+    ) -> SearchOperation {
         SearchOperation {
-            op: op,
-            pb: OpSpinner::default(op),
+            op,
             target: target.to_string(),
             directory: directory.to_string(),
             operate_curr_file: false,
+            protection,
+            executor,
         }
     }
 
     fn operate(&mut self) -> Result<(), OperationError> {
         let dir_clone = self.directory.clone();
         let target_dir = Path::new(&dir_clone);
-        match rrc_lib::recurse_on_paths(self, vec![target_dir], true) {
-            Ok(_) => Ok(()),
+        let walk = WalkOptions {
+            recurse: true,
+            protection: Protection::none(),
+            ..Default::default()
+        };
+        match rrc_lib::recurse_on_paths(self, vec![target_dir], &walk) {
+            Ok(_) => {
+                self.executor.print_dry_run();
+                Ok(())
+            }
             Err(e) => {
+                if let Some(protected) = e.protected() {
+                    output::print_protected_hint(protected);
+                }
                 let file = e.file.clone();
                 Err(OperationError::new(Box::new(e), self.op, Some(file)))
             }
         }
     }
 
-    fn run_op_single(&mut self, path: &PathBuf) -> std::io::Result<()> {
+    fn run_op_single(&self, path: &Path) -> std::io::Result<()> {
         match self.op {
             OPERATION::DELETE => files::remove_file_or_empty_dir(path)?,
             OPERATION::SHRED { num_runs } => {
-                let file = OpenOptions::new()
-                    .write(true)
-                    .create(false)
-                    .read(true)
-                    .open(path)?;
-
-                files::overwrite_file(&file, num_runs)?;
-                files::remove_file_or_empty_dir(path)?;
+                let options = ShredOptions {
+                    ow_options: OverwriteOptions {
+                        runs: num_runs,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                };
+                shred::shred_path(path, &options, &mut ())?;
             }
-            OPERATION::TRASH => trash::delete_all([path]).unwrap(),
+            OPERATION::TRASH => trash::delete_all([path]).map_err(io::Error::other)?,
             _ => {}
         }
 
@@ -427,24 +1336,39 @@ impl SearchOperation {
 }
 
 impl RecursiveCallback for SearchOperation {
-    fn cb(&mut self, path: &PathBuf) -> Result<bool, FileErr> {
+    fn cb(&mut self, path: &Path) -> Result<bool, FileErr> {
         if self.operate_curr_file {
-            self.run_op_single(path);
+            //Trashing a directory takes everything inside it along, so anything marked in there counts too
+            self.protection
+                .check(path, false)
+                .and_then(|_| match self.op {
+                    OPERATION::TRASH => self.protection.check_tree(path),
+                    _ => self.protection.check_marked(path),
+                })
+                .map_err(|e| FileErr::map(io::Error::other(e), path))?;
+            let res = match self.op {
+                OPERATION::TRASH => self
+                    .executor
+                    .on_tree(self.op, path, || self.run_op_single(path)),
+                _ => self
+                    .executor
+                    .on_path(self.op, path, || self.run_op_single(path)),
+            };
+            res.map_err(|e| FileErr::map(e, path))?;
             self.operate_curr_file = false;
         }
         Ok(true)
     }
 
-    fn display_cb(&mut self, path: &PathBuf, is_dir: bool) -> bool {
-        let file_name = &files::os_str_to_str(path.file_name().unwrap());
-        if levenshtein(&file_name, &self.target) <= 1 {
-            let selection = output::prompt_search_operation(
-                &self.target,
-                &file_name.to_string(),
-                is_dir,
-                self.op,
-            )
-            .unwrap();
+    fn display_cb(&mut self, path: &Path, is_dir: bool) -> bool {
+        //The directory searched can be given as . or .., which have no name to match
+        let Some(file_name) = path.file_name() else {
+            return true;
+        };
+        let file_name = &files::os_str_to_str(file_name);
+        if levenshtein(file_name, &self.target) <= 1 {
+            let selection =
+                output::prompt_search_operation(&self.target, file_name, is_dir).unwrap();
             if selection.0 {
                 self.operate_curr_file = true;
             }
@@ -456,10 +1380,398 @@ impl RecursiveCallback for SearchOperation {
     }
 }
 
-fn check_recursion<'a>(paths: &Vec<&Path>, recurse_default: bool) -> bool {
+//Held for reading by operations which leave something behind if they're cut off, which a second interrupt waits
+//for before exiting
+static CLEANUP: RwLock<()> = RwLock::new(());
+
+///Set once Ctrl-C is pressed or SIGTERM is received, which operations check to stop after what they're in the
+/// middle of. Asking a second time exits straight away, unless something is being cleaned up, in which case it
+/// exits as soon as that's done
+fn interrupt_flag() -> Arc<AtomicBool> {
+    static INTERRUPT: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+    INTERRUPT
+        .get_or_init(|| {
+            let flag = Arc::new(AtomicBool::new(false));
+            let handler_flag = flag.clone();
+            //If the handler can't be installed the signals just keep their default behaviour of exiting
+            let _ = ctrlc::set_handler(move || {
+                if handler_flag.swap(true, Ordering::Relaxed) {
+                    let _cleaned_up = CLEANUP.write().unwrap_or_else(PoisonError::into_inner);
+                    std::process::exit(130);
+                }
+            });
+            flag
+        })
+        .clone()
+}
+
+///Keeps track of how far a shred or delete got, so that it can be resumed if it gets interrupted
+#[derive(Default)]
+struct ResumeTracker {
+    //Where the state of a resumed operation came from. Fresh operations use the default location
+    state_path: Option<PathBuf>,
+    //The state being resumed from, which the completed paths carry over from
+    previous: Option<ResumeState>,
+    skip: HashSet<PathBuf>,
+    completed: Mutex<Vec<PathBuf>>,
+    interrupted: Mutex<Vec<InterruptedFile>>,
+}
+
+impl ResumeTracker {
+    fn resuming(state: ResumeState, state_path: PathBuf) -> ResumeTracker {
+        ResumeTracker {
+            state_path: Some(state_path),
+            skip: state.completed.iter().cloned().collect(),
+            completed: Mutex::new(state.completed.clone()),
+            previous: Some(state),
+            interrupted: Mutex::default(),
+        }
+    }
+
+    fn complete(&self, path: &Path) {
+        self.completed.lock().unwrap().push(absolute(path));
+    }
+
+    fn interrupt(&self, path: &Path, pass: usize) {
+        self.interrupted.lock().unwrap().push(InterruptedFile {
+            path: absolute(path),
+            pass,
+        });
+    }
+
+    fn is_completed(&self, path: &Path) -> bool {
+        !self.skip.is_empty() && self.skip.contains(&absolute(path))
+    }
+
+    fn first_pass(&self, path: &Path) -> usize {
+        self.previous
+            .as_ref()
+            .map_or(1, |s| s.first_pass(&absolute(path)))
+    }
+
+    ///Writes the resume state of an interrupted operation and tells the user how to resume it
+    fn save_interrupted(&self, op: OPERATION, paths: &[&Path], recurse: bool) {
+        let state = ResumeState {
+            args: match &self.previous {
+                Some(s) => s.args.clone(),
+                None => wild::args().collect(),
+            },
+            cwd: match &self.previous {
+                Some(s) => s.cwd.clone(),
+                None => env::current_dir().unwrap_or_default(),
+            },
+            recurse,
+            completed: self.completed.lock().unwrap().clone(),
+            pending: paths
+                .iter()
+                .filter(|p| fs::symlink_metadata(p).is_ok())
+                .map(|p| absolute(p))
+                .collect(),
+            interrupted: self.interrupted.lock().unwrap().clone(),
+            time: chrono::Utc::now(),
+        };
+
+        let saved = match self.state_path() {
+            Some(path) => state.write(&path).map(|_| path),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "there is no state directory to save it in",
+            )),
+        };
+        output::print_interrupted(op, &state, saved);
+    }
+
+    ///Removes the state of a resumed operation once it has finished
+    fn finish(&self) {
+        if let (Some(_), Some(path)) = (&self.previous, self.state_path()) {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    fn state_path(&self) -> Option<PathBuf> {
+        self.state_path.clone().or_else(resume::default_state_path)
+    }
+}
+
+///A path an operation failed on and kept going past
+pub struct Failure {
+    pub path: String,
+    pub operation: OPERATION,
+    pub kind: io::ErrorKind,
+}
+
+///Collects the failures of an operation which was told to keep going instead of stopping at the first one
+struct FailureLog {
+    operation: OPERATION,
+    keep_going: bool,
+    failures: Mutex<Vec<Failure>>,
+}
+
+impl FailureLog {
+    fn new(operation: OPERATION, keep_going: bool) -> FailureLog {
+        FailureLog {
+            operation,
+            keep_going,
+            failures: Mutex::default(),
+        }
+    }
+
+    ///Records the failure if the operation is keeping going, otherwise hands the error back to stop it
+    fn record(&self, pb: &OpSpinner, err: FileErr) -> Result<(), FileErr> {
+        if !self.keep_going {
+            return Err(err);
+        }
+        pb.print_failed_warn(&err);
+        self.failures.lock().unwrap().push(Failure {
+            kind: err.kind(),
+            path: err.file,
+            operation: self.operation,
+        });
+        Ok(())
+    }
+
+    ///Prints a summary of the failures, which are then returned as an error so that the process exits non-zero
+    fn finish(&self) -> Result<(), OperationError> {
+        let failures = self.failures.lock().unwrap();
+        if failures.is_empty() {
+            return Ok(());
+        }
+        output::print_failures(&failures);
+        Err(OperationError::new(
+            Box::new(io::Error::other(format!(
+                "{} path(s) could not be {}",
+                failures.len(),
+                self.operation.to_past()
+            ))),
+            self.operation,
+            None,
+        ))
+    }
+}
+
+//Device a file is on, or None on platforms without device numbers
+#[cfg(unix)]
+fn device_of(metadata: io::Result<fs::Metadata>) -> Option<u64> {
+    Some(std::os::unix::fs::MetadataExt::dev(&metadata.ok()?))
+}
+
+#[cfg(not(unix))]
+fn device_of(_metadata: io::Result<fs::Metadata>) -> Option<u64> {
+    None
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+///Continues the shred or delete an interrupted run left its state for
+fn resume(state_file: Option<&String>, dry_run: bool) -> Result<(), OperationError> {
+    let op_error =
+        |e: Box<dyn Error>, file: Option<String>| OperationError::new(e, OPERATION::RESUME, file);
+
+    let state_path = match state_file {
+        Some(f) => PathBuf::from(f),
+        None => resume::default_state_path().ok_or_else(|| {
+            op_error(
+                Box::new(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "there is no state directory, give the state file to resume from",
+                )),
+                None,
+            )
+        })?,
+    };
+    let state_name = Some(path_to_string(&state_path));
+    let state =
+        ResumeState::read(&state_path).map_err(|e| op_error(Box::new(e), state_name.clone()))?;
+
+    //Relative paths in the original arguments, like the report, are relative to where it was run from
+    env::set_current_dir(&state.cwd)
+        .map_err(|e| op_error(Box::new(e), Some(path_to_string(&state.cwd))))?;
+    let args = crate::build_cli()
+        .try_get_matches_from(&state.args)
+        .map_err(|e| op_error(Box::new(e), state_name.clone()))?;
+
+    let pending = state.pending.clone();
+    let paths = pending.iter().map(PathBuf::as_path).collect();
+    let recurse = state.recurse;
+    let walk = |m: &ArgMatches| -> Result<WalkOptions, OperationError> {
+        Ok(WalkOptions {
+            recurse,
+            ..walk_options_from_args(m, OPERATION::RESUME)?
+        })
+    };
+    match args.subcommand() {
+        Some(("shred", m)) => {
+            apply_io_priority(m);
+            let mut op = shred_operation_from_args(m);
+            //The interrupted run already wrote a report of what it got through, which is carried on rather than
+            //written over with only what's shredded from here on
+            if let Some(target) = &mut op.report {
+                match ShredReport::read(&target.path) {
+                    Ok(report) => target.builder = ReportBuilder::resuming(report),
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    Err(e) => {
+                        return Err(op_error(Box::new(e), Some(path_to_string(&target.path))))
+                    }
+                }
+            }
+            op.resume = ResumeTracker::resuming(state, state_path);
+            op.executor = Executor::new(dry_run);
+            op.run(paths, walk(m)?)
+        }
+        Some(("delete", m)) => {
+            apply_io_priority(m);
+            let mut op = delete_operation_from_args(m);
+            op.resume = ResumeTracker::resuming(state, state_path);
+            op.executor = Executor::new(dry_run);
+            op.run(paths, walk(m)?)
+        }
+        _ => Err(op_error(
+            Box::new(io::Error::other(
+                "only interrupted shreds and deletes can be resumed",
+            )),
+            state_name,
+        )),
+    }
+}
+
+///Carries out a plan written with --plan-out, refusing any entries which have changed since it was made
+fn apply(plan_file: &str, dry_run: bool) -> Result<(), OperationError> {
+    let op_error =
+        |e: Box<dyn Error>| OperationError::new(e, OPERATION::APPLY, Some(plan_file.to_string()));
+
+    let plan = Plan::read(Path::new(plan_file)).map_err(|e| op_error(Box::new(e)))?;
+    //The options the plan is applied with come from the arguments it was made with
+    env::set_current_dir(&plan.cwd).map_err(|e| op_error(Box::new(e)))?;
+    let args = crate::build_cli()
+        .try_get_matches_from(&plan.args)
+        .map_err(|e| op_error(Box::new(e)))?;
+
+    let trash_items = match plan.entries.iter().any(|e| e.trash_id.is_some()) {
+        true => os_limited::list().map_err(|e| op_error(Box::new(e)))?,
+        false => Vec::new(),
+    };
+    let find_item = |entry: &PlanEntry| {
+        let id = entry.trash_id.as_ref()?;
+        trash_items.iter().find(|i| i.id.to_string_lossy() == *id)
+    };
+
+    let stale = plan.stale_entries(|entry| match entry.trash_id {
+        Some(_) => find_item(entry)
+            .ok_or(Stale::Missing)
+            .and_then(|i| files::trash_item_backing_path(i).ok_or(Stale::Unverifiable)),
+        None => Ok(entry.path.clone()),
+    });
+    output::print_stale_entries(
+        &stale
+            .iter()
+            .map(|(i, reason)| (&plan.entries[*i], *reason))
+            .collect::<Vec<_>>(),
+    );
+
+    let entries: Vec<&PlanEntry> = plan
+        .entries
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !stale.iter().any(|(s, _)| s == i))
+        .map(|(_, e)| e)
+        .collect();
+    let paths: Vec<&Path> = entries.iter().map(|e| e.path.as_path()).collect();
+    let items: Vec<TrashItem> = entries
+        .iter()
+        .filter_map(|e| find_item(e))
+        .cloned()
+        .collect();
+    let executor = Executor::new(dry_run);
+
+    //Every target in the plan has already been resolved and filtered, so none of them are walked into or filtered
+    //again
+    let unfiltered = |walk: WalkOptions| WalkOptions {
+        filter: Filter::default(),
+        ..walk
+    };
+    match args.subcommand() {
+        Some(("trash", m)) => TrashOperation::trash(
+            paths.iter().map(path_to_string).collect(),
+            WalkOptions {
+                protection: protection_from_args(m, OPERATION::APPLY)?,
+                ..Default::default()
+            },
+            executor,
+        ),
+        Some(("restore", _)) => {
+            RestoreOperation::restore(items, OpSpinner::default(OPERATION::RESTORE), executor)
+        }
+        Some(("purge", m)) => {
+            let all_files = m.get_flag("all");
+            BasicOperations::purge_items(
+                items,
+                all_files,
+                purge_shred_options_from_args(m),
+                executor,
+                OpSpinner::default(OPERATION::PURGE { all_files }),
+            )
+        }
+        Some(("delete", m)) => {
+            let mut op = delete_operation_from_args(m);
+            op.executor = executor;
+            op.run(
+                paths,
+                unfiltered(walk_options_from_args(m, OPERATION::APPLY)?),
+            )
+        }
+        Some(("shred", m)) => {
+            let mut op = shred_operation_from_args(m);
+            op.executor = executor;
+            op.run(
+                paths,
+                unfiltered(walk_options_from_args(m, OPERATION::APPLY)?),
+            )
+        }
+        _ => Err(op_error(Box::new(io::Error::other(
+            "the plan isn't for an operation which can be applied",
+        )))),
+    }?;
+
+    if stale.is_empty() {
+        Ok(())
+    } else {
+        Err(op_error(Box::new(io::Error::other(format!(
+            "{} stale entry(s) were refused",
+            stale.len()
+        )))))
+    }
+}
+
+fn verify_report(report_path: &str) -> Result<(), OperationError> {
+    let op_error = |e: Box<dyn Error>| {
+        OperationError::new(e, OPERATION::VERIFYREPORT, Some(report_path.to_string()))
+    };
+
+    let report = ShredReport::read(Path::new(report_path)).map_err(|e| op_error(Box::new(e)))?;
+    let verification = report::verify_report(&report).map_err(|e| op_error(Box::new(e)))?;
+    output::print_report_verification(&report, &verification);
+
+    if verification.passed() {
+        Ok(())
+    } else {
+        Err(op_error(Box::new(io::Error::other(
+            "the report could not be verified",
+        ))))
+    }
+}
+
+fn check_recursion(paths: &[&Path], recurse_default: bool, symlinks: SymlinkPolicy) -> bool {
+    let walk = WalkOptions {
+        recurse: true,
+        symlinks,
+        protection: Protection::none(),
+        ..Default::default()
+    };
     if !recurse_default {
         for path in paths {
-            if path.is_dir() {
+            if files::is_walkable_dir(path, &walk) {
                 return prompt_recursion(path_to_string(path)).is_ok_and(|v| v);
             }
         }
@@ -5,11 +5,22 @@ use std::{
 };
 
 use colored::Colorize;
-use rrc_lib::files;
+use rrc_lib::{
+    files::{self, OverwriteObserver},
+    filesystem::OverwriteConcern,
+    freespace::WipedSpace,
+    plan::{PlanEntry, Stale},
+    protect::{ProtectedKind, ProtectedPath},
+    report::{ReportVerification, ShredReport},
+    resume::ResumeState,
+    FileErr,
+};
 
 use chrono::TimeZone;
 use dialoguer::{theme::ColorfulTheme, Select};
-use indicatif::{ProgressBar, ProgressFinish, ProgressStyle};
+use indicatif::{
+    BinaryBytes, DecimalBytes, MultiProgress, ProgressBar, ProgressFinish, ProgressStyle,
+};
 use prettytable::{
     cell,
     format::{self, FormatBuilder},
@@ -17,10 +28,13 @@ use prettytable::{
 };
 use trash::TrashItem;
 
-use crate::operations::OPERATION;
+use crate::{
+    executor::PlannedAction,
+    operations::{Failure, OPERATION},
+};
 
 impl OPERATION {
-    pub fn to_infinitive(&self) -> String {
+    pub fn to_infinitive(self) -> String {
         match self {
             OPERATION::DELETE => "deleting",
             OPERATION::TRASH => "trashing",
@@ -28,10 +42,16 @@ impl OPERATION {
             OPERATION::SHRED { num_runs: _ } => "shredding",
             OPERATION::LIST => "listing",
             OPERATION::PURGE { all_files: _ } => "purging",
+            OPERATION::WIPEFREE => "wiping free space on",
+            OPERATION::VERIFYREPORT => "verifying report",
+            OPERATION::RESUME => "resuming from",
+            OPERATION::APPLY => "applying plan",
+            OPERATION::PROTECT => "protecting",
+            OPERATION::UNPROTECT => "unprotecting",
         }
         .into()
     }
-    pub fn to_past(&self) -> String {
+    pub fn to_past(self) -> String {
         match self {
             OPERATION::DELETE => "deleted",
             OPERATION::TRASH => "trashed",
@@ -39,6 +59,12 @@ impl OPERATION {
             OPERATION::SHRED { num_runs: _ } => "shredded",
             OPERATION::LIST => "listed",
             OPERATION::PURGE { all_files: _ } => "purged",
+            OPERATION::WIPEFREE => "wiped free space on",
+            OPERATION::VERIFYREPORT => "verified report",
+            OPERATION::RESUME => "resumed from",
+            OPERATION::APPLY => "applied plan",
+            OPERATION::PROTECT => "protected",
+            OPERATION::UNPROTECT => "unprotected",
         }
         .into()
     }
@@ -59,27 +85,31 @@ pub struct TrashList {
     max_width: u16,
 }
 
+//Box drawn table format shared by the tables printed
+fn box_format() -> format::TableFormat {
+    FormatBuilder::new()
+        .column_separator('│')
+        .borders('│')
+        .separator(
+            format::LinePosition::Top,
+            format::LineSeparator::new('─', '┬', '┌', '┐'),
+        )
+        .separator(
+            format::LinePosition::Bottom,
+            format::LineSeparator::new('─', '┴', '└', '┘'),
+        )
+        .separator(
+            format::LinePosition::Intern,
+            format::LineSeparator::new('─', '┼', '├', '┤'),
+        )
+        .padding(1, 1)
+        .build()
+}
+
 impl Default for TrashList {
     fn default() -> Self {
-        let format = FormatBuilder::new()
-            .column_separator('│')
-            .borders('│')
-            .separator(
-                format::LinePosition::Top,
-                format::LineSeparator::new('─', '┬', '┌', '┐'),
-            )
-            .separator(
-                format::LinePosition::Bottom,
-                format::LineSeparator::new('─', '┴', '└', '┘'),
-            )
-            .separator(
-                format::LinePosition::Intern,
-                format::LineSeparator::new('─', '┼', '├', '┤'),
-            )
-            .padding(1, 1)
-            .build();
         let mut table = Table::new();
-        table.set_format(format);
+        table.set_format(box_format());
         table.set_titles(row![b->"Name", b->"Path", b->"Date"]);
 
         Self {
@@ -90,7 +120,7 @@ impl Default for TrashList {
 }
 
 impl TrashList {
-    pub fn set_items(&mut self, items: &Vec<TrashItem>) {
+    pub fn set_items(&mut self, items: &[TrashItem]) {
         items.iter().for_each(|i| {
             self.table.add_row(Self::row_from_trash_item(i));
         });
@@ -201,10 +231,9 @@ pub fn prompt_recursion(path: String) -> Result<bool, dialoguer::Error> {
 }
 
 pub fn prompt_search_operation(
-    query: &String,
-    match_name: &String,
+    query: &str,
+    match_name: &str,
     is_dir: bool,
-    op: OPERATION,
 ) -> Result<(bool, bool), dialoguer::Error> {
     let dir_string = match is_dir {
         true => "directory",
@@ -218,7 +247,7 @@ pub fn prompt_search_operation(
         .interact()?;
 
     let cont = dialoguer::Confirm::with_theme(&ColorfulTheme::default())
-        .with_prompt(format!("Continue searching?"))
+        .with_prompt("Continue searching?")
         .interact()?;
 
     Ok((op, cont))
@@ -247,11 +276,11 @@ pub fn run_conflict_prompt(items: Vec<TrashItem>) -> TrashItem {
 }
 
 pub fn file_conflict_prompt(prompt: String, items: Vec<String>) -> usize {
-    return Select::with_theme(&ColorfulTheme::default())
+    Select::with_theme(&ColorfulTheme::default())
         .with_prompt(prompt)
         .items(&items)
         .interact()
-        .unwrap();
+        .unwrap()
 }
 
 ///Capitalises the first letter of any valid ASCII string
@@ -287,19 +316,20 @@ impl OpSpinner {
         self.pb.enable_steady_tick(Duration::from_millis(150))
     }
 
-    pub fn set_file_str<'a, S>(&self, file: S)
+    pub fn set_file_str<S>(&self, file: S)
     where
         S: Into<Cow<'static, str>>,
     {
         self.pb.set_message(file)
     }
 
-    pub fn set_file_path<P: AsRef<Path>>(&self, path: P) {
-        self.pb.set_message(files::path_to_string(path))
+    ///Moves the spinner into a MultiProgress so other bars can be drawn underneath it
+    pub fn attach(&self, multi: &MultiProgress) {
+        multi.add(self.pb.clone());
     }
 
-    pub fn print_error_msg<S: Colorize>(&self, msg: S) {
-        self.pb.println(format!("{}", msg.red().bold()))
+    pub fn set_file_path<P: AsRef<Path>>(&self, path: P) {
+        self.pb.set_message(files::path_to_string(path))
     }
 
     pub fn print_warn_msg<S: Colorize>(&self, msg: S) {
@@ -311,6 +341,53 @@ impl OpSpinner {
         self.print_warn_msg(format!("{} does not exist, skipping...", file.as_ref()).as_str())
     }
 
+    pub fn print_mount_skipped_warn(&self, path: &Path) {
+        self.print_warn_msg(
+            format!(
+                "{} is on a different filesystem, skipping...",
+                files::path_to_string(path)
+            )
+            .as_str(),
+        )
+    }
+
+    pub fn print_overwrite_concern_warn(&self, concern: &OverwriteConcern) {
+        self.print_warn_msg(
+            format!(
+                "Warning: {} ({}): {}",
+                files::path_to_string(&concern.mount_point),
+                concern.fs_type,
+                concern.reason
+            )
+            .as_str(),
+        )
+    }
+
+    pub fn print_protected_skipped_warn(&self, protected: &ProtectedPath) {
+        self.print_warn_msg(format!("{protected}, skipping...").as_str())
+    }
+
+    pub fn print_not_marked_warn(&self, path: &Path) {
+        self.print_warn_msg(
+            format!(
+                "{} isn't marked as protected, skipping...",
+                files::path_to_string(path)
+            )
+            .as_str(),
+        )
+    }
+
+    pub fn print_failed_warn(&self, err: &FileErr) {
+        self.print_warn_msg(
+            format!(
+                "Error while {} {}: {err}, skipping...",
+                self.op.to_infinitive(),
+                err.file
+            )
+            .as_str(),
+        )
+    }
+
     pub fn auto_finish(&self, n: usize) {
         self.finish();
         let op_string = capitalise_ascii(self.op.to_past());
@@ -335,3 +412,368 @@ impl OpSpinner {
         self.pb.finish_and_clear();
     }
 }
+
+///Warns about filesystems which overwriting files on might not destroy their contents
+pub fn print_overwrite_concerns(concerns: &[OverwriteConcern], strict: bool) {
+    for concern in concerns {
+        println!(
+            "{} {} ({}): {}",
+            "Warning:".yellow().bold(),
+            files::path_to_string(&concern.mount_point),
+            concern.fs_type,
+            concern.reason
+        );
+    }
+
+    if !concerns.is_empty() && !strict {
+        println!(
+            "{}",
+            "Overwriting files there may leave their original contents recoverable. Use --strict to refuse instead."
+                .yellow()
+        );
+    }
+}
+
+///Prints notes about files which needed special handling during a shred
+pub fn print_shred_notes(linked_files: usize, sparse_files: usize, undiscarded_files: usize) {
+    if linked_files > 0 {
+        println!(
+            "{}",
+            format!("{linked_files} file(s) had other hard links, which still exist and now point at the overwritten data")
+                .yellow()
+        );
+    }
+    if sparse_files > 0 {
+        println!(
+            "{}",
+            format!("{sparse_files} sparse file(s) only had their allocated extents overwritten")
+                .yellow()
+        );
+    }
+    if undiscarded_files > 0 {
+        println!(
+            "{}",
+            format!("{undiscarded_files} file(s) could not be discarded, their filesystem doesn't support punching holes")
+                .yellow()
+        );
+    }
+}
+
+///Lists the mount points a walk staying on one filesystem didn't descend into
+pub fn print_skipped_mounts(mounts: &[path::PathBuf]) {
+    if mounts.is_empty() {
+        return;
+    }
+    println!(
+        "{}",
+        format!(
+            "Skipped {} mount point(s) on other filesystems, the directories containing them were left in place:",
+            mounts.len()
+        )
+        .yellow()
+    );
+    for mount in mounts {
+        println!("  {}", files::path_to_string(mount));
+    }
+}
+
+///Lists the paths marked as protected which a walk found and left alone
+pub fn print_skipped_marked(marked: &[path::PathBuf]) {
+    if marked.is_empty() {
+        return;
+    }
+    println!(
+        "{}",
+        format!(
+            "Skipped {} path(s) marked as protected, the directories containing them were left in place:",
+            marked.len()
+        )
+        .yellow()
+    );
+    for path in marked {
+        println!("  {}", files::path_to_string(path));
+    }
+}
+
+///Prints the failures an operation kept going past, grouped by the operation and what went wrong
+pub fn print_failures(failures: &[Failure]) {
+    if failures.is_empty() {
+        return;
+    }
+
+    let mut groups: Vec<(OPERATION, std::io::ErrorKind, Vec<&str>)> = Vec::new();
+    for failure in failures {
+        match groups
+            .iter_mut()
+            .find(|(op, kind, _)| *op == failure.operation && *kind == failure.kind)
+        {
+            Some((_, _, paths)) => paths.push(&failure.path),
+            None => groups.push((failure.operation, failure.kind, vec![&failure.path])),
+        }
+    }
+
+    let mut table = Table::new();
+    table.set_format(box_format());
+    table.set_titles(row![b->"Operation", b->"Error", b->"Count", b->"Paths"]);
+    for (op, kind, paths) in groups {
+        table.add_row(row![
+            capitalise_ascii(op.to_infinitive()),
+            kind,
+            r->paths.len(),
+            paths.join("\n")
+        ]);
+    }
+
+    println!(
+        "{}",
+        format!(
+            "{} path(s) failed and were skipped along with the directories containing them:",
+            failures.len()
+        )
+        .red()
+    );
+    table.printstd();
+}
+
+///Lists what a dry run would have done, with the number of paths and their total size
+pub fn print_dry_run(planned: &[PlannedAction]) {
+    if !planned.is_empty() {
+        let mut table = Table::new();
+        table.set_format(box_format());
+        table.set_titles(row![b->"Action", b->"Path", b->"Size"]);
+        for action in planned {
+            table.add_row(row![
+                capitalise_ascii(action.operation.to_infinitive()),
+                action.target,
+                r->BinaryBytes(action.size)
+            ]);
+        }
+        table.printstd();
+    }
+
+    let total: u64 = planned.iter().map(|a| a.size).sum();
+    println!(
+        "{}",
+        format!(
+            "Dry run, nothing was changed. {} path(s) totalling {} would have been affected",
+            planned.len(),
+            BinaryBytes(total)
+        )
+        .bold()
+    );
+}
+
+pub fn print_io_priority_warning(err: &std::io::Error) {
+    println!(
+        "{}",
+        format!("Could not set the I/O priority, continuing without it: {err}").yellow()
+    );
+}
+
+///Prints how much free space a wipe-free run overwrote
+pub fn print_wipe_free_result(wiped: &WipedSpace) {
+    let bytes = DecimalBytes(wiped.bytes);
+    if wiped.interrupted {
+        println!(
+            "{}",
+            format!(
+                "Interrupted after wiping {bytes} of free space, the fill files have been removed"
+            )
+            .yellow()
+        );
+    } else {
+        OpSpinner::print_success(format!("Wiped {bytes} of free space").as_str());
+    }
+}
+
+///Prints what an interrupted shred or delete got done, and how to pick it back up
+pub fn print_interrupted(
+    op: OPERATION,
+    state: &ResumeState,
+    saved: std::io::Result<std::path::PathBuf>,
+) {
+    println!(
+        "{}",
+        format!(
+            "Interrupted after {} file(s) were {}, {} path(s) are still pending",
+            state.completed.len(),
+            op.to_past(),
+            state.pending.len()
+        )
+        .yellow()
+    );
+    for file in &state.interrupted {
+        println!(
+            "{} {}",
+            format!("Stopped during pass {}:", file.pass).yellow(),
+            files::path_to_string(&file.path)
+        );
+    }
+
+    match saved {
+        Ok(path) => println!(
+            "Run rrc resume to continue, the state was saved to {}",
+            files::path_to_string(path)
+        ),
+        Err(e) => println!(
+            "{}",
+            format!("Could not save the state to resume from: {e}").red()
+        ),
+    }
+}
+
+///Lists everything marked as protected if that's why an operation was refused, and says how to get past it
+pub fn print_protected_hint(protected: &ProtectedPath) {
+    if let ProtectedKind::Marked(marked) = &protected.kind {
+        println!("{}", "Marked as protected:".yellow());
+        for path in marked {
+            println!("  {}", files::path_to_string(path));
+        }
+    }
+    println!(
+        "{}",
+        "Pass --allow-protected <PATH> to operate on it anyway, or --no-preserve-root to turn off every protection"
+            .yellow()
+    );
+}
+
+pub fn print_report_written(path: &Path) {
+    println!("Report written to {}", files::path_to_string(path));
+}
+
+pub fn print_plan_written(path: &Path, entries: usize, res: std::io::Result<()>) {
+    match res {
+        Ok(()) => println!(
+            "Plan of {entries} action(s) written to {}, run rrc apply {} to carry it out",
+            files::path_to_string(path),
+            files::path_to_string(path)
+        ),
+        Err(e) => println!(
+            "{}",
+            format!(
+                "Could not write the plan to {}: {e}",
+                files::path_to_string(path)
+            )
+            .red()
+        ),
+    }
+}
+
+///Lists the plan entries which won't be applied because their targets aren't what was planned for
+pub fn print_stale_entries(stale: &[(&PlanEntry, Stale)]) {
+    if stale.is_empty() {
+        return;
+    }
+    println!(
+        "{}",
+        format!("Refusing {} stale plan entry(s):", stale.len()).red()
+    );
+    for (entry, reason) in stale {
+        println!("  {} {reason}", files::path_to_string(&entry.path));
+    }
+}
+
+///Prints the outcome of checking a shred report, listing any files which still exist
+pub fn print_report_verification(report: &ShredReport, verification: &ReportVerification) {
+    for path in &verification.remaining {
+        println!(
+            "{} {}",
+            "Still exists:".red().bold(),
+            files::path_to_string(path)
+        );
+    }
+
+    match verification.hash_matches {
+        Some(true) => println!("Summary hash matches the report"),
+        Some(false) => println!(
+            "{}",
+            "Summary hash does not match, the report has been modified".red()
+        ),
+        None => println!("{}", "The report has no summary hash".yellow()),
+    }
+
+    if verification.passed() {
+        OpSpinner::print_success(
+            format!(
+                "Verified {} file(s) shredded on {} at {}",
+                report.records.len(),
+                report.host,
+                report.created.format(LONG_DATE_FORMAT)
+            )
+            .as_str(),
+        );
+    }
+}
+
+///Byte level progress of a shred, drawn underneath the OpSpinner of the operation. Shows the progress
+/// through the current file and through the whole run
+pub struct ShredProgress {
+    file: Option<ProgressBar>,
+    total: ProgressBar,
+}
+
+impl ShredProgress {
+    ///The per file bar only makes sense when files are shredded one at a time, so it can be left out
+    pub fn new(spinner: &OpSpinner, show_file: bool) -> Self {
+        let multi = MultiProgress::new();
+        spinner.attach(&multi);
+
+        let file =
+            show_file.then(|| multi.add(ProgressBar::new(0).with_style(Self::style("File"))));
+        let total = multi.add(ProgressBar::new(0).with_style(Self::style("Total")));
+
+        Self { file, total }
+    }
+
+    fn style(label: &str) -> ProgressStyle {
+        ProgressStyle::default_bar()
+            .template(&format!(
+                "  {label:<5} [{{bar:30.green}}] {{decimal_bytes}}/{{decimal_total_bytes}} {{decimal_bytes_per_sec}} ETA {{eta}} {{msg}}"
+            ))
+            .unwrap()
+            .progress_chars("=> ")
+    }
+
+    ///Sets the number of bytes that will be written over the whole run, i.e the size of every file times the number of passes
+    pub fn set_total(&self, bytes: u64) {
+        self.total.set_length(bytes);
+    }
+
+    ///Shows the bandwidth limit next to the effective rate, so it's clear why it isn't any faster
+    pub fn set_limit(&self, rate: Option<u64>) {
+        if let Some(rate) = rate {
+            self.total
+                .set_message(format!("(limit {}/s)", BinaryBytes(rate)));
+        }
+    }
+
+    pub fn finish(&self) {
+        if let Some(file) = &self.file {
+            file.finish_and_clear();
+        }
+        self.total.finish_and_clear();
+    }
+}
+
+//Implemented on a reference so that several workers can report to the same bars at once
+impl OverwriteObserver for &ShredProgress {
+    fn overwrite_started(&mut self, file_len: u64, runs: usize) {
+        if let Some(file) = &self.file {
+            file.reset();
+            file.set_length(file_len * runs as u64);
+        }
+    }
+
+    fn pass_started(&mut self, pass: usize, runs: usize) {
+        if let Some(file) = &self.file {
+            file.set_message(format!("pass {pass}/{runs}"));
+        }
+    }
+
+    fn bytes_written(&mut self, bytes: u64) {
+        if let Some(file) = &self.file {
+            file.inc(bytes);
+        }
+        self.total.inc(bytes);
+    }
+}